@@ -21,109 +21,396 @@
 // SOFTWARE.
 
 
-use crate::msg::Msg;
-use std::{collections::{HashMap, HashSet}, io::Error};
+use crate::{bark::{RequestInterceptor, ResponseHook}, msg::{Msg, PushType}};
+use std::{collections::HashSet, io::Error, sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::{Duration, Instant}};
+use indexmap::IndexMap;
 use reqwest::header::HeaderValue;
 
-const APNS_HOST: &str = "api.push.apple.com";
+/// first wait before retrying a 5xx response, doubled on every further attempt
+const SERVER_ERROR_BACKOFF: Duration = Duration::from_millis(500);
+/// longest wait between two retries of a 5xx response
+const SERVER_ERROR_MAX_BACKOFF: Duration = Duration::from_secs(60);
 
-/// send msg to devices
+/// why a device failed
+#[derive(Clone, Debug)]
+pub struct Failure {
+    /// http status returned by the server, None if no response was received
+    pub status: Option<u16>,
+    /// response body or error message
+    pub reason: String,
+}
+
+impl Failure {
+    pub fn new(status: Option<u16>, reason: String) -> Self {
+        Self { status, reason }
+    }
+}
+
+/// the apns server to send to and how
+#[derive(Clone)]
+pub struct Target {
+    pub host: &'static str,
+    pub port: u16,
+    pub topic: String,
+    pub token: String,
+    pub server_error_retries: u32,
+    pub middleware: Middleware,
+    /// sent instead of the serialized msg
+    pub payload: Option<String>,
+    /// shared by every request, its http/2 connection to apns is reused across sends
+    pub client: reqwest::Client,
+}
+
+impl Target {
+    pub fn url(&self, device: &str) -> String {
+        format!("https://{host}:{port}/3/device/{device}", host = self.host, port = self.port, device = device)
+    }
+
+    /// the broadcast endpoint of the app
+    pub fn broadcast_url(&self, topic: &str) -> String {
+        format!("https://{host}:{port}/4/broadcasts/apps/{topic}", host = self.host, port = self.port, topic = topic)
+    }
+}
+
+/// user code run around every request
+#[derive(Clone, Default)]
+pub struct Middleware {
+    /// run in order on every request just before it is sent
+    pub interceptors: Vec<Arc<dyn RequestInterceptor>>,
+    /// run in order on every response before its status is checked
+    pub response_hooks: Vec<Arc<dyn ResponseHook>>,
+    /// log every request and response
+    pub trace: bool,
+    /// requests sent and not answered yet, shared by the clones
+    pub in_flight: Arc<AtomicUsize>,
+    /// give up on a request not answered within this time, None waits as long as the client does
+    pub timeout: Option<Duration>,
+}
+
+/// counts a request as in flight until dropped
+struct InFlight<'a>(&'a AtomicUsize);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Middleware {
+    /// run the interceptors and send, counting the request as in flight until it is answered
+    pub async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response, reqwest::Error> {
+        let req: reqwest::RequestBuilder = match self.timeout {
+            Some(timeout) => self.intercept(req).timeout(timeout),
+            None => self.intercept(req),
+        };
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        let _in_flight: InFlight<'_> = InFlight(&self.in_flight);
+        req.send().await
+    }
+
+    /// run the interceptors in order
+    pub fn intercept(&self, req: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let req: reqwest::RequestBuilder = self.interceptors.iter().fold(req, |req, interceptor| interceptor.intercept(req));
+        if !self.trace {
+            return req;
+        }
+        // build a clone to log, the original still carries any build error to send
+        if let Some(Ok(built)) = req.try_clone().map(|req| req.build()) {
+            trace(&describe_request(&built));
+        }
+        req
+    }
+
+    pub fn inspect(&self, device: &str, resp: &reqwest::Response) {
+        if self.trace {
+            let mut lines: String = format!("<-- {} {} ({})", resp.status(), resp.url(), device);
+            for (name, value) in resp.headers() {
+                lines += &format!("\n{}: {}", name, String::from_utf8_lossy(value.as_bytes()));
+            }
+            trace(&lines);
+        }
+        for hook in self.response_hooks.iter() {
+            hook.on_response(device, resp);
+        }
+    }
+
+    /// log a response body when tracing
+    pub fn inspect_body(&self, body: &str) {
+        if self.trace {
+            trace(&format!("<-- body: {}", body));
+        }
+    }
+}
+
+/// the method, url, headers and body of a request, the bearer token cut after its first 20 chars
+pub fn describe_request(req: &reqwest::Request) -> String {
+    let mut lines: String = format!("--> {} {}", req.method(), req.url());
+    for (name, value) in req.headers() {
+        let value: String = String::from_utf8_lossy(value.as_bytes()).to_string();
+        let value: String = match value.strip_prefix("Bearer ") {
+            Some(token) if name == reqwest::header::AUTHORIZATION && token.len() > 20 => format!("Bearer {}...", &token[..20]),
+            _ => value,
+        };
+        lines += &format!("\n{}: {}", name, value);
+    }
+    if let Some(body) = req.body().and_then(|body| body.as_bytes()) {
+        lines += &format!("\n\n{}", String::from_utf8_lossy(body));
+    }
+    lines
+}
+
+fn trace(lines: &str) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!("{}", lines);
+    #[cfg(not(feature = "tracing"))]
+    eprintln!("{}", lines);
+}
+
+/// async send to devices
 /// 
-/// return: None if success, or a vector of failed devices
-pub fn send<T>(msg: &Msg, topic: &str, token: &str, devices: T) -> Option<Vec<String>> 
+/// return: the failed devices and why they failed, empty if all success
+pub async fn async_send<T>(msg: &Msg, target: &Target, devices: T) -> IndexMap<String, Failure> 
 where 
     T: IntoIterator<Item = String>
 {
-    let rt: Result<tokio::runtime::Runtime, Error> = tokio::runtime::Runtime::new();
-    match rt {
-        Ok(rt) => {
-            rt.block_on(
-                async_send(msg, topic, token, devices)
-            )
-        },
-        Err(e)=> {
-            eprintln!("send failed: {}", e);
-            Some(devices.into_iter().map(|device| device.to_string()).collect())
-        }
-    }
+    timed_send(msg, target, devices).await.0
 }
 
-/// async send to devices
+/// how long the phases of a send took
+#[derive(Default)]
+pub struct Timings {
+    pub serialize: Duration,
+    /// wall time per device, including retries
+    pub devices: Vec<(String, Duration)>,
+}
+
+/// async send to devices, measuring how long each phase took
 /// 
-/// return: None if success, or a vector of failed devices
-pub async fn async_send<T>(msg: &Msg, topic: &str, token: &str, devices: T) -> Option<Vec<String>> 
+/// return: the failed devices and why they failed, empty if all success, and the timings
+pub async fn timed_send<T>(msg: &Msg, target: &Target, devices: T) -> (IndexMap<String, Failure>, Timings)
 where 
     T: IntoIterator<Item = String>
 {
     let devices: Vec<String> = devices.into_iter().collect::<Vec<_>>(); 
-    match do_send(msg, topic, token, devices.clone().into_iter()).await {
-        Ok(results) => {
-            if results.is_empty() {
-                return None;
+    let mut timings: Timings = Timings::default();
+    match do_send(&target.client, msg, target, devices.clone().into_iter(), &mut timings).await {
+        Ok(results) => (results, timings),
+        Err(e) => {
+            eprintln!("all failed: {}", e);
+            (devices.into_iter().map(|device| (device, Failure::new(None, e.to_string()))).collect(), timings)
+        }
+    }
+}
+
+/// result of a silent registration check
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Registration {
+    /// apns accepted the notification
+    Registered,
+    /// apns answered 410 Unregistered
+    Unregistered,
+    /// apns answered 400 BadDeviceToken
+    BadDeviceToken,
+    /// any other failure, the registration could not be confirmed
+    Unknown,
+}
+
+/// new the http/2 client used to talk to apns
+pub fn client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::ClientBuilder::new().http2_prior_knowledge().build()
+}
+
+/// send a silent background notification to check the device token is still registered
+pub async fn verify(client: &reqwest::Client, target: &Target, device: &str) -> Registration {
+    match probe(client, target, device).await {
+        Ok((sc, _)) if (200..300).contains(&sc) => Registration::Registered,
+        Ok((410, text)) => {
+            println!("device {} is no longer registered: 410 {}", device, text);
+            Registration::Unregistered
+        },
+        Ok((400, text)) if text.contains("BadDeviceToken") => {
+            println!("device {} is not a valid token: 400 {}", device, text);
+            Registration::BadDeviceToken
+        },
+        Ok((sc, text)) => {
+            eprintln!("verify {} failed: {} {}", device, sc, text);
+            Registration::Unknown
+        },
+        Err(e) => {
+            eprintln!("verify {} failed: {}", device, e);
+            Registration::Unknown
+        }
+    }
+}
+
+/// send a silent background notification and return the raw answer
+/// 
+/// return: (status, body)
+pub async fn probe(client: &reqwest::Client, target: &Target, device: &str) -> Result<(u16, String), reqwest::Error> {
+    let req: reqwest::RequestBuilder = 
+            client
+                .post(target.url(device))
+                .bearer_auth(&target.token)
+                .header("apns-topic", &target.topic)
+                .header("apns-push-type", "background")
+                .header("apns-priority", "5")
+                .body("{\"aps\":{\"content-available\":1}}");
+    let resp: reqwest::Response = target.middleware.send(req).await?;
+    target.middleware.inspect(device, &resp);
+    let sc: u16 = resp.status().as_u16();
+    Ok((sc, resp.text().await.unwrap_or_default()))
+}
+
+/// broadcast to every device of the app `target.topic` subscribed to `channel`
+/// 
+/// apns only broadcasts live activity updates, the push type is always liveactivity
+/// 
+/// return: the failure if apns did not accept the broadcast
+pub async fn broadcast(msg: &Msg, target: &Target, channel: &str) -> Result<(), Failure> {
+    let body: String = match &target.payload {
+        Some(payload) => payload.clone(),
+        None => msg.try_serialize().map_err(|e| Failure::new(None, e.to_string()))?,
+    };
+    let mut req: reqwest::RequestBuilder = 
+            target.client
+                .post(target.broadcast_url(&target.topic))
+                .bearer_auth(&target.token)
+                .header("apns-channel-id", channel)
+                .header("apns-push-type", "liveactivity")
+                .body(body);
+    if let Some(priority) = msg.priority() {
+        req = req.header("apns-priority", HeaderValue::from(priority as u16));
+    }
+    match target.middleware.send(req).await {
+        Ok(resp) => {
+            target.middleware.inspect(channel, &resp);
+            if resp.status().is_success() {
+                return Ok(());
             }
-            Some(results.keys().map(|device| device.to_string()).collect::<Vec<String>>())
+            let sc: u16 = resp.status().as_u16();
+            let text: String = resp.text().await.unwrap_or_default();
+            target.middleware.inspect_body(&text);
+            eprintln!("broadcast to {} failed: {} {}", channel, sc, text);
+            Err(Failure::new(Some(sc), text))
         },
         Err(e) => {
-            eprintln!("all failed: {}", e.to_string());
-            Some(devices)
+            eprintln!("broadcast to {} failed: {}", channel, e);
+            Err(Failure::new(None, e.to_string()))
         }
     }
-        
+}
+
+/// the error and its sources, the http/2 error (e.g. a FLOW_CONTROL_ERROR reset) is only in the sources
+fn error_chain(e: &reqwest::Error) -> String {
+    let mut reason: String = e.to_string();
+    let mut source: Option<&dyn std::error::Error> = std::error::Error::source(e);
+    while let Some(e) = source {
+        reason += &format!(": {}", e);
+        source = e.source();
+    }
+    reason
+}
+
+/// wait before the retry after `attempt` retries, capped so many retries can not overflow
+fn server_error_backoff(attempt: u32) -> Duration {
+    SERVER_ERROR_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(SERVER_ERROR_MAX_BACKOFF)
 }
 
 /// do send to real device
-async fn do_send<T>(msg: &Msg, topic: &str, token: &str, devices: T) -> Result<HashMap<String, String>, Error>
+/// 
+/// 5xx responses are retried up to `server_error_retries` times with exponential backoff,
+/// 4xx responses fail immediately
+async fn do_send<T>(client: &reqwest::Client, msg: &Msg, target: &Target, devices: T, timings: &mut Timings) -> Result<IndexMap<String, Failure>, Error>
 where 
     T: Iterator<Item = String>
 {
-    let client: reqwest::Client = reqwest::ClientBuilder::new().http2_prior_knowledge().build().unwrap();
     let mut headers = reqwest::header::HeaderMap::new();
-    headers.insert("apns-topic", HeaderValue::from_str(topic).unwrap());
+    let push_type: PushType = msg.push_type();
+    let topic: &str = msg.topic().unwrap_or(&target.topic);
+    let topic: String = if push_type == PushType::PushToTalk && !topic.ends_with(".voip-ptt") {
+        format!("{}.voip-ptt", topic)
+    } else {
+        topic.to_string()
+    };
+    headers.insert("apns-topic", HeaderValue::from_str(&topic).map_err(Error::other)?);
     if let Some(msg_id) = msg.get_id() {
-        headers.insert("apns-collapse-id", HeaderValue::from_str(&msg_id).unwrap());
+        headers.insert("apns-collapse-id", HeaderValue::from_str(&msg_id).map_err(Error::other)?);
     }
-    if msg.is_deleted() && msg.get_id().is_some() {
-        headers.insert("apns-push-type", HeaderValue::from_str("background").unwrap());
-    } else {
-        headers.insert("apns-push-type", HeaderValue::from_str("alert").unwrap());
-    }
-
-    let mut results: HashMap<String, String> = HashMap::new();
-    let body: String = msg.serialize();
-    let devices: HashSet<String> = devices.collect::<HashSet<String>>();
-    for device  in devices {
-        let resp = 
-                client
-                    .post(format!("https://{host}/3/device/{device}", host = APNS_HOST, device = device))
-                    .bearer_auth(token)
-                    .headers(headers.clone())
-                    .body(body.clone())
-                    .send().await;
-        match resp {
-            Ok(resp) => {
-                if ! resp.status().is_success() {
-                    let sc = resp.status().as_u16().to_string();
-                    if let Some(len) = resp.content_length() {
-                        if len > 2 {
-                            match resp.text().await {
-                                Ok(text) => {
-                                    println!("resp body: {}", text);
-                                    results.insert(device.to_string(), sc + text.as_str());
-                                },
-                                Err(e) => {
-                                    eprint!("{}", e.to_string());
-                                    results.insert(device.to_string(), sc + e.to_string().as_str());
-                                }
+    if let Some(channel) = msg.channel_id() {
+        headers.insert("apns-channel-id", HeaderValue::from_str(channel).map_err(Error::other)?);
+    }
+    headers.insert("apns-push-type", HeaderValue::from_static(push_type.as_str()));
+    if let Some(priority) = msg.priority() {
+        headers.insert("apns-priority", HeaderValue::from(priority as u16));
+    }
+
+    let mut results: IndexMap<String, Failure> = IndexMap::new();
+    let start: Instant = Instant::now();
+    let body: String = match &target.payload {
+        Some(payload) => payload.clone(),
+        None => msg.try_serialize().map_err(Error::other)?,
+    };
+    timings.serialize = start.elapsed();
+    let mut unique: HashSet<String> = HashSet::new();
+    for device in devices.filter(|device| unique.insert(device.clone())) {
+        let start: Instant = Instant::now();
+        let mut attempt: u32 = 0;
+        loop {
+            let req: reqwest::RequestBuilder = 
+                    client
+                        .post(target.url(&device))
+                        .bearer_auth(&target.token)
+                        .headers(headers.clone())
+                        .body(body.clone());
+            let resp = target.middleware.send(req).await;
+            match resp {
+                Ok(resp) => {
+                    target.middleware.inspect(&device, &resp);
+                    if resp.status().is_server_error() && attempt < target.server_error_retries {
+                        eprintln!("send to {} got {}, retrying", device, resp.status());
+                        tokio::time::sleep(server_error_backoff(attempt)).await;
+                        attempt += 1;
+                        continue;
+                    }
+                    if ! resp.status().is_success() {
+                        let sc: u16 = resp.status().as_u16();
+                        match resp.text().await {
+                            Ok(text) => {
+                                target.middleware.inspect_body(&text);
+                                println!("resp body: {}", text);
+                                results.insert(device.to_string(), Failure::new(Some(sc), text));
+                            },
+                            Err(e) => {
+                                eprint!("{}", e);
+                                results.insert(device.to_string(), Failure::new(Some(sc), e.to_string()));
                             }
                         }
                     }
+                },
+                Err(e) => {
+                    let reason: String = error_chain(&e);
+                    eprintln!("send to {} failed: {}", device, reason);
+                    results.insert(device.to_string(), Failure::new(None, reason));
                 }
-            },
-            Err(e) => {
-                eprintln!("send to {} failed: {}", device, e.to_string());
-                results.insert(device.to_string(), e.to_string());
             }
+            break;
         }
+        timings.devices.push((device, start.elapsed()));
     }
     Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_error_backoff() {
+        assert_eq!(server_error_backoff(0), Duration::from_millis(500));
+        assert_eq!(server_error_backoff(2), Duration::from_secs(2));
+        assert_eq!(server_error_backoff(32), SERVER_ERROR_MAX_BACKOFF);
+        assert_eq!(server_error_backoff(u32::MAX), SERVER_ERROR_MAX_BACKOFF);
+    }
 }
\ No newline at end of file
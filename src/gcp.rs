@@ -0,0 +1,139 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+//! load bark credentials from gcp secret manager, see [`Bark::new_from_gcp_secret_manager`]
+
+use crate::{bark::Bark, config::{parse_flat_json, ConfigValue}, secrets::{answer, json_value}, BarkError};
+
+const SCOPE: &str = "https://www.googleapis.com/auth/cloud-platform";
+const SECRET_MANAGER_URL: &str = "https://secretmanager.googleapis.com/v1";
+/// used on compute engine, cloud run and gke when `GOOGLE_APPLICATION_CREDENTIALS` is not set
+const METADATA_TOKEN_URL: &str = "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// fetch the payload of a secret version
+pub(crate) async fn access_secret(project_id: &str, secret_name: &str, version: &str) -> Result<String, BarkError> {
+    let client: reqwest::Client = reqwest::Client::new();
+    let access_token: String = access_token(&client).await?;
+    let url: String = format!("{}/projects/{}/secrets/{}/versions/{}:access", SECRET_MANAGER_URL, project_id, secret_name, version);
+    let body: String = answer(client.get(url).bearer_auth(access_token), "secret manager").await?;
+    let data: &str = json_value(&body, "payload")
+        .and_then(|payload| json_value(payload, "data"))
+        .map(|data| data.trim_matches('"'))
+        .ok_or_else(|| BarkError::Request(format!("secret manager answered without a payload: {}", body)))?;
+    let data: Vec<u8> = openssl::base64::decode_block(data)
+        .map_err(|e| BarkError::Request(format!("secret payload is not base64: {}", e)))?;
+    String::from_utf8(data).map_err(|e| BarkError::InvalidConfiguration(format!("secret is not utf-8: {}", e)))
+}
+
+/// an oauth2 access token for the service account in `GOOGLE_APPLICATION_CREDENTIALS`,
+/// or the one attached to the instance when it is not set
+async fn access_token(client: &reqwest::Client) -> Result<String, BarkError> {
+    let body: String = match std::env::var("GOOGLE_APPLICATION_CREDENTIALS") {
+        Ok(path) => {
+            let credentials: String = tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|e| BarkError::InvalidConfiguration(format!("read {} failed: {}", path, e)))?;
+            let (token_uri, assertion) = service_account_assertion(&credentials, Bark::ts())?;
+            let form: String = format!("grant_type=urn%3Aietf%3Aparams%3Aoauth%3Agrant-type%3Ajwt-bearer&assertion={}", assertion);
+            let request: reqwest::RequestBuilder = client
+                .post(token_uri)
+                .header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(form);
+            answer(request, "gcp token endpoint").await?
+        },
+        Err(_) => answer(client.get(METADATA_TOKEN_URL).header("Metadata-Flavor", "Google"), "gcp metadata server").await?,
+    };
+    Bark::json_field(&body, "access_token")
+        .map(str::to_string)
+        .ok_or_else(|| BarkError::Request(format!("no access token in {}", body)))
+}
+
+/// sign the RS256 jwt exchanged for an access token, only service account keys are supported
+/// 
+/// return : (token_uri, jwt)
+fn service_account_assertion(credentials: &str, time_stamp: u64) -> Result<(String, String), BarkError> {
+    let entries: Vec<(String, ConfigValue)> = parse_flat_json(credentials)?;
+    let field = |name: &str| -> Option<&str> {
+        entries.iter().find_map(|(key, value)| match value {
+            ConfigValue::Str(value) if key == name => Some(value.as_str()),
+            _ => None,
+        })
+    };
+    if field("type") != Some("service_account") {
+        return Err(BarkError::InvalidConfiguration("only service account credentials are supported".to_string()));
+    }
+    let client_email: &str = field("client_email").ok_or_else(|| BarkError::InvalidConfiguration("credentials have no client_email".to_string()))?;
+    let private_key: &str = field("private_key").ok_or_else(|| BarkError::InvalidConfiguration("credentials have no private_key".to_string()))?;
+    let token_uri: &str = field("token_uri").unwrap_or(DEFAULT_TOKEN_URI);
+
+    let encode = |str: String| Bark::clean_str(openssl::base64::encode_block(str.as_bytes()));
+    let unsigned: String = format!(
+        "{}.{}",
+        encode("{\"alg\":\"RS256\",\"typ\":\"JWT\"}".to_string()),
+        encode(format!(
+            "{{\"iss\":\"{}\",\"scope\":\"{}\",\"aud\":\"{}\",\"iat\":{},\"exp\":{}}}",
+            client_email, SCOPE, token_uri, time_stamp, time_stamp + 3600
+        ))
+    );
+    let key: openssl::pkey::PKey<openssl::pkey::Private> = openssl::pkey::PKey::private_key_from_pem(private_key.as_bytes())
+        .map_err(|e| BarkError::InvalidConfiguration(format!("invalid service account key: {}", e)))?;
+    let mut signer: openssl::sign::Signer<'_> = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
+    signer.update(unsigned.as_bytes())?;
+    let signature: String = Bark::clean_str(openssl::base64::encode_block(&signer.sign_to_vec()?));
+    Ok((token_uri.to_string(), format!("{}.{}", unsigned, signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape(str: &str) -> String {
+        str.replace('\n', "\\n")
+    }
+
+    #[test]
+    fn test_service_account_assertion() {
+        let key: openssl::pkey::PKey<openssl::pkey::Private> = openssl::pkey::PKey::from_rsa(openssl::rsa::Rsa::generate(2048).unwrap()).unwrap();
+        let pem: String = String::from_utf8(key.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        let credentials: String = format!(
+            "{{\"type\": \"service_account\", \"client_email\": \"bark@project.iam.gserviceaccount.com\", \"private_key\": \"{}\"}}",
+            escape(&pem)
+        );
+        let (token_uri, jwt) = service_account_assertion(&credentials, 1_700_000_000).unwrap();
+        assert_eq!(token_uri, DEFAULT_TOKEN_URI);
+
+        let (unsigned, signature) = jwt.rsplit_once('.').unwrap();
+        let claims: String = Bark::decode_segment(unsigned.split('.').nth(1).unwrap()).unwrap();
+        assert_eq!(Bark::json_field(&claims, "iss"), Some("bark@project.iam.gserviceaccount.com"));
+        assert_eq!(Bark::json_field(&claims, "exp"), Some("1700003600"));
+        let signature: Vec<u8> = openssl::base64::decode_block(&format!(
+            "{}{}", signature.replace('-', "+").replace('_', "/"), "=".repeat((4 - signature.len() % 4) % 4)
+        )).unwrap();
+        let mut verifier: openssl::sign::Verifier<'_> = openssl::sign::Verifier::new(openssl::hash::MessageDigest::sha256(), &key).unwrap();
+        verifier.update(unsigned.as_bytes()).unwrap();
+        assert!(verifier.verify(&signature).unwrap());
+
+        assert!(service_account_assertion("{\"type\": \"authorized_user\"}", 0).is_err());
+    }
+}
\ No newline at end of file
@@ -27,23 +27,24 @@
 /// [bark]: https://github.com/finb/bark
 /// # Example
 /// ```rust
-/// use bark::{bark::Bark, msg::Msg};
+/// use bark_dev::{bark::{Bark, SendResult}, msg::Msg};
 /// 
 /// let mut bark: Bark = Bark::new();
 /// 
-/// let msg = Msg::new("notify".to_string(), "hello world".to_string());
+/// let msg = Msg::new("notify", "hello world");
 /// 
-/// let devices: Vec<String> = vec!["the_device_token_get_from_bark_app"];
+/// let devices: Vec<String> = vec!["the_device_token_get_from_bark_app".to_string()];
 /// 
-/// let send_reult: Option<Vec<String>> = None;//bark.send(&msg, &devices);
+/// let send_reult: Vec<SendResult> = Vec::new();//bark.send(&msg, devices);
 /// 
-/// // send result is None if success
-/// assert!(send_reult.is_none());
-/// 
-/// // send result is a vector of failed devices if failed
-/// if let Some(failed_devices) = send_reult {
-///     // do something
-///     println!("send failed: {:?}", failed_devices);
+/// // send result has one entry per device, with why it failed
+/// for result in send_reult.iter() {
+///     if let Some(timestamp) = result.unregistered_at {
+///         // the app was removed from the device, drop the device
+///         println!("{} is unregistered since {}", result.device, timestamp);
+///     } else if !result.success {
+///         println!("send to {} failed: {:?} {:?}", result.device, result.status_code, result.apns_reason);
+///     }
 /// };
 /// ```
 /// # Note
@@ -58,10 +59,109 @@
 /// 
 /// and call `bark.born(time_stamp, token)` to new a bark instance with the token
 /// 
+/// or let bark do both with a [`bark::TokenStore`], e.g. `bark.with_token_store(FileTokenStore(path))`
+/// 
 /// 
 /// # Features
 /// - [x] send push notifications to iOS devices which install the #bark# app using the APNS protocol.
 /// - [x] async send push notificationsto iOS devices which install the #bark# app using the APNS protocol
+/// - [x] send through a self-hosted bark server when `BARK_SERVER_URL` is set
+/// - [x] load credentials from gcp secret manager with the `gcp` feature
+/// - [x] load credentials from a hashicorp vault kv v2 secret with the `vault` feature
 pub mod bark;
 mod apns;
-pub mod msg;
\ No newline at end of file
+mod server;
+mod config;
+pub mod msg;
+pub mod registry;
+pub mod pipeline;
+#[cfg(feature = "tower")]
+pub mod service;
+#[cfg(any(feature = "gcp", feature = "vault"))]
+mod secrets;
+#[cfg(feature = "gcp")]
+mod gcp;
+#[cfg(feature = "vault")]
+mod vault;
+#[cfg(test)]
+mod test_server;
+
+use std::fmt::Display;
+
+/// errors returned by bark
+#[derive(Debug)]
+pub enum BarkError {
+    /// a [`bark::BarkCheckpoint`] could not be parsed or restored
+    InvalidCheckpoint(String),
+    /// a configuration value is not supported
+    InvalidConfiguration(String),
+    /// the request could not be sent or the answer was not understood
+    Request(String),
+    /// no connection could be established to the server
+    ConnectionFailed(String),
+    /// a device token is neither 64 hex chars nor 32 base64 encoded bytes
+    InvalidDeviceToken(String),
+    /// the apns provider token could not be signed with the auth key
+    TokenGenerationFailed(openssl::error::ErrorStack),
+    /// the message could not be encrypted
+    EncryptionFailed(openssl::error::ErrorStack),
+    /// the http client could not be built
+    HttpClientCreationFailed(reqwest::Error),
+    /// apns or the bark server answered with an error status
+    SendFailed { device: String, status: u16, reason: String },
+    /// the message can not be serialized, e.g. it is missing encryption settings
+    InvalidPayload(String),
+}
+
+impl Display for BarkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BarkError::InvalidCheckpoint(reason) => write!(f, "invalid checkpoint: {}", reason),
+            BarkError::InvalidConfiguration(reason) => write!(f, "invalid configuration: {}", reason),
+            BarkError::Request(reason) => write!(f, "request failed: {}", reason),
+            BarkError::ConnectionFailed(reason) => write!(f, "connection failed: {}", reason),
+            BarkError::InvalidDeviceToken(reason) => write!(f, "invalid device token: {}", reason),
+            BarkError::TokenGenerationFailed(e) => write!(f, "token generation failed: {}", e),
+            BarkError::EncryptionFailed(e) => write!(f, "encryption failed: {}", e),
+            BarkError::HttpClientCreationFailed(e) => write!(f, "http client creation failed: {}", e),
+            BarkError::SendFailed { device, status, reason } => write!(f, "send to {} failed: {} {}", device, status, reason),
+            BarkError::InvalidPayload(reason) => write!(f, "invalid payload: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for BarkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BarkError::TokenGenerationFailed(e) | BarkError::EncryptionFailed(e) => Some(e),
+            BarkError::HttpClientCreationFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+/// openssl is only used directly to sign the apns token, encryption errors are mapped to
+/// [`BarkError::EncryptionFailed`] where they happen
+impl From<openssl::error::ErrorStack> for BarkError {
+    fn from(e: openssl::error::ErrorStack) -> Self {
+        BarkError::TokenGenerationFailed(e)
+    }
+}
+
+impl From<reqwest::Error> for BarkError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_builder() {
+            BarkError::HttpClientCreationFailed(e)
+        } else if e.is_connect() {
+            BarkError::ConnectionFailed(e.to_string())
+        } else {
+            BarkError::Request(e.to_string())
+        }
+    }
+}
+
+impl From<msg::MsgError> for BarkError {
+    fn from(e: msg::MsgError) -> Self {
+        BarkError::InvalidPayload(e.to_string())
+    }
+}
\ No newline at end of file
@@ -25,6 +25,14 @@ use std::fmt::Display;
 
 use openssl::symm::{Cipher, Crypter, Mode};
 
+use crate::{config::{parse_flat_json, ConfigValue, JsonStr}, BarkError};
+
+#[cfg(feature = "serde")]
+mod serde_impl;
+
+/// The category registered by the Bark app, it provides the copy action button.
+const DEFAULT_CATEGORY: &str = "myNotificationCategory";
+
 /// Push Notification Message structure.
 ///
 /// This struct represents a push notification message that can be sent to devices.
@@ -32,7 +40,7 @@ use openssl::symm::{Cipher, Crypter, Mode};
 ///
 /// # Example
 /// ```rust
-/// use bark::msg::Msg;
+/// use bark_dev::msg::{Level, Msg};
 ///
 /// // new a simple message with title and body
 /// let msg = Msg::new("title", "body");
@@ -45,6 +53,15 @@ use openssl::symm::{Cipher, Crypter, Mode};
 /// msg.set_badge(1);
 /// // and so on
 /// ```
+///
+/// `Msg` is `Clone`, so callbacks or background tasks that need to inspect a message
+/// can take an owned copy instead of borrowing the original.
+///
+/// # Security
+/// With the `serde` feature, serializing a `Msg` writes every field as is, including the
+/// encryption `key` and the `hmac_secret` in clear text. Treat the output as a secret,
+/// or clear them before serializing a message that leaves the process.
+#[derive(Clone)]
 pub struct Msg {
     /// Push Title
     title: String,
@@ -99,8 +116,159 @@ pub struct Msg {
     id: Option<String>,
     /// delete flag
     is_deleted: Option<bool>,
+    /// Image shown while the app launches from the notification
+    launch_image: Option<String>,
+    /// Notification category, selects the action buttons registered by the app
+    category: String,
+    /// Secret used to sign the payload with HMAC-SHA256
+    hmac_secret: Option<Vec<u8>>,
+    /// Region code for server side filtering, not used by APNS
+    region: Option<String>,
+    /// Locale for server side filtering, not used by APNS
+    locale: Option<String>,
+    /// Media attachment url, a Bark server extension, not used by APNS
+    attachment_url: Option<String>,
+    /// Image shown with the notification by the Bark app, not used by APNS
+    image: Option<String>,
+    /// Ring like a phone call in the Bark app, not used by APNS
+    call: Option<u8>,
+    /// Video shown with the notification by the Bark app, not used by APNS
+    video: Option<String>,
+    /// Audio played with the notification by the Bark app, not used by APNS
+    audio: Option<String>,
+    /// Preview image for a video or audio, not used by APNS
+    thumbnail: Option<String>,
+    /// `apns-push-type` header, derived from the message if not set
+    push_type: Option<PushType>,
+    /// `apns-priority` header, APNS defaults to 10
+    priority: Option<u8>,
+    /// `apns-topic` header, overrides the topic of the `Bark` instance
+    topic: Option<String>,
+    /// `apns-channel-id` header, the broadcast channel the message is sent to
+    channel_id: Option<String>,
+    /// Bark `urgency` field, also sets the `apns-priority`
+    urgency: Option<NotificationUrgency>,
+}
+
+/// A violated invariant of a [`Msg`], see [`Msg::is_valid`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MsgError {
+    /// A field the message needs is not set, e.g. the key of an encrypted message.
+    MissingField(&'static str),
+    /// The key does not have the length of the encryption type.
+    KeyLength { expected: usize, actual: usize },
+    /// The IV does not have the length of the encryption type and mode.
+    IvLength { expected: usize, actual: usize },
+    /// The id is 64 bytes or longer.
+    IdTooLong(usize),
+    /// The encryption type can only be set once.
+    EncryptTypeAlreadySet,
+    /// The encryption mode can only be set once.
+    EncryptModeAlreadySet,
+    /// A payload could not be read into a message, see [`Msg::from_bark_webhook`].
+    InvalidPayload(String),
+}
+
+impl Display for MsgError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MsgError::MissingField(field) => write!(f, "missing {}", field),
+            MsgError::KeyLength { expected, actual } => write!(f, "key must be {} bytes, got {}", expected, actual),
+            MsgError::IvLength { expected, actual } => write!(f, "iv must be {} bytes, got {}", expected, actual),
+            MsgError::IdTooLong(len) => write!(f, "id must be shorter than 64 bytes, got {}", len),
+            MsgError::EncryptTypeAlreadySet => write!(f, "encryption type can only be set once"),
+            MsgError::EncryptModeAlreadySet => write!(f, "encryption mode can only be set once"),
+            MsgError::InvalidPayload(reason) => write!(f, "invalid payload: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for MsgError {}
+
+/// The kind of media in a [`RichMediaContent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MediaType {
+    Image,
+    Video,
+    Audio,
+}
+
+/// Media the Bark app's notification service extension downloads and shows with the notification.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RichMediaContent {
+    /// An absolute `http` or `https` URL of the media.
+    pub url: String,
+    /// An absolute `http` or `https` URL of a preview image, used for video and audio.
+    pub thumbnail_url: Option<String>,
+    pub media_type: MediaType,
+}
+
+/// The `apns-push-type` of a notification.
+///
+/// alert: Default value, a visible notification.
+///
+/// background: A silent notification waking the app, used to delete notifications.
+///
+/// push to talk: A Push to Talk (`voip-ptt`) notification, iOS 16+.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PushType {
+    Alert,
+    Background,
+    PushToTalk,
+}
+
+impl PushType {
+    /// Returns the value of the `apns-push-type` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PushType::Alert => "alert",
+            PushType::Background => "background",
+            PushType::PushToTalk => "voip-ptt",
+        }
+    }
+}
+
+
+/// How urgently a notification should be delivered, see [`Msg::set_urgency`].
+///
+/// The urgency sets the `apns-priority` header and the Bark `urgency` field:
+/// `VeryLow` is priority 1, delivery may be delayed or dropped to save power,
+/// `Low` is priority 5, delivered when the device is awake, grouped with others,
+/// `Normal`, `High` and `Critical` are priority 10, delivered immediately.
+///
+/// The urgency only affects when the notification reaches the device. Whether it breaks
+/// through a Focus mode is decided by the interruption level ([`Level`]): only a
+/// `timeSensitive` notification is shown during Focus, if the user allows it for the app.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum NotificationUrgency {
+    VeryLow,
+    Low,
+    Normal,
+    High,
+    Critical,
 }
 
+impl NotificationUrgency {
+    /// The value sent as the Bark `urgency` field.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationUrgency::VeryLow => "veryLow",
+            NotificationUrgency::Low => "low",
+            NotificationUrgency::Normal => "normal",
+            NotificationUrgency::High => "high",
+            NotificationUrgency::Critical => "critical",
+        }
+    }
+
+    /// The value sent as the `apns-priority` header.
+    pub fn priority(&self) -> u8 {
+        match self {
+            NotificationUrgency::VeryLow => 1,
+            NotificationUrgency::Low => 5,
+            NotificationUrgency::Normal | NotificationUrgency::High | NotificationUrgency::Critical => 10,
+        }
+    }
+}
 
 /// Notification level
 /// 
@@ -109,7 +277,7 @@ pub struct Msg {
 /// timeSensitive: Time-sensitive notification, can be displayed while in focus mode.
 /// 
 /// passive: Only adds the notification to the notification list, will not display on the screen.
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum Level {
     ACTIVE,
     TIMESENSITIVE,
@@ -117,6 +285,7 @@ pub enum Level {
 }
 
 impl Level {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(str: &str) -> Option<Self> {
         match str.to_lowercase().as_str() {
             "timesensitive" => Some(Self::TIMESENSITIVE),
@@ -127,19 +296,24 @@ impl Level {
     }
 }
 
+impl Level {
+    /// The value sent as `interruption-level`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Level::ACTIVE => "active",
+            Level::TIMESENSITIVE => "timeSensitive",
+            Level::PASSIVE =>"passive"
+        }
+    }
+}
+
 impl Display for Level {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let str = 
-            match self {
-                Level::ACTIVE => "active",
-                Level::TIMESENSITIVE => "timeSensitive",
-                Level::PASSIVE =>"passive"
-            };
-        write!(f, "{}", str)
+        write!(f, "{}", self.as_str())
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EncryptMode {
     CBC,
     ECB,
@@ -147,6 +321,7 @@ pub enum EncryptMode {
 }
 
 impl EncryptMode {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(str: &str) -> Option<Self> {
         if str.is_empty() {
             return None;
@@ -160,7 +335,7 @@ impl EncryptMode {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EncryptType {
     AES128,
     AES192,
@@ -168,6 +343,7 @@ pub enum EncryptType {
 }
 
 impl EncryptType {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(str: &str) -> Option<Self> {
         if str.is_empty() {
             return None;
@@ -179,6 +355,190 @@ impl EncryptType {
             _ => None,
         }
     }
+
+    /// Picks the encryption type matching the key length.
+    ///
+    /// 16 bytes is `AES128`, 24 bytes is `AES192` and 32 bytes is `AES256`.
+    ///
+    /// # Returns
+    /// The matching [`EncryptType`], or `None` for any other length.
+    pub fn detect_from_key_length(key: &str) -> Option<Self> {
+        match key.len() {
+            16 => Some(EncryptType::AES128),
+            24 => Some(EncryptType::AES192),
+            32 => Some(EncryptType::AES256),
+            _ => None,
+        }
+    }
+
+    /// Returns the key length in bytes, 16 for `AES128`, 24 for `AES192` and 32 for `AES256`.
+    pub fn key_len(&self) -> usize {
+        match self {
+            EncryptType::AES128 => 16,
+            EncryptType::AES192 => 24,
+            EncryptType::AES256 => 32,
+        }
+    }
+}
+
+/// Messages are equal when they produce the same notification.
+///
+/// The `cipher` is derived from the encryption type and mode, so it is not compared.
+impl PartialEq for Msg {
+    fn eq(&self, other: &Self) -> bool {
+        // no `..`, so a new field does not compile until it is compared or skipped here
+        let Msg {
+            title, body, level, badge, auto_copy, copy, sound, icon, group, is_archive, url, iv,
+            enc_type, mode, key, cipher: _, id, is_deleted, launch_image, category, hmac_secret,
+            region, locale, attachment_url, image, call, video, audio, thumbnail, push_type,
+            priority, topic, channel_id, urgency,
+        } = self;
+        *title == other.title
+            && *body == other.body
+            && *level == other.level
+            && *badge == other.badge
+            && *auto_copy == other.auto_copy
+            && *copy == other.copy
+            && *sound == other.sound
+            && *icon == other.icon
+            && *group == other.group
+            && *is_archive == other.is_archive
+            && *url == other.url
+            && *iv == other.iv
+            && *enc_type == other.enc_type
+            && *mode == other.mode
+            && *key == other.key
+            && *id == other.id
+            && *is_deleted == other.is_deleted
+            && *launch_image == other.launch_image
+            && *category == other.category
+            && *hmac_secret == other.hmac_secret
+            && *region == other.region
+            && *locale == other.locale
+            && *attachment_url == other.attachment_url
+            && *image == other.image
+            && *call == other.call
+            && *video == other.video
+            && *audio == other.audio
+            && *thumbnail == other.thumbnail
+            && *push_type == other.push_type
+            && *priority == other.priority
+            && *topic == other.topic
+            && *channel_id == other.channel_id
+            && *urgency == other.urgency
+    }
+}
+
+impl Eq for Msg {}
+
+/// Secrets (`key` and the HMAC secret) are redacted.
+impl std::fmt::Debug for Msg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let redacted = |secret: bool| if secret { Some("<redacted>") } else { None };
+        f.debug_struct("Msg")
+            .field("title", &self.title)
+            .field("body", &self.body)
+            .field("level", &self.level)
+            .field("badge", &self.badge)
+            .field("auto_copy", &self.auto_copy)
+            .field("copy", &self.copy)
+            .field("sound", &self.sound)
+            .field("icon", &self.icon)
+            .field("group", &self.group)
+            .field("is_archive", &self.is_archive)
+            .field("url", &self.url)
+            .field("iv", &self.iv)
+            .field("enc_type", &self.enc_type)
+            .field("mode", &self.mode)
+            .field("key", &redacted(self.key.is_some()))
+            .field("id", &self.id)
+            .field("is_deleted", &self.is_deleted)
+            .field("launch_image", &self.launch_image)
+            .field("category", &self.category)
+            .field("hmac_secret", &redacted(self.hmac_secret.is_some()))
+            .field("region", &self.region)
+            .field("locale", &self.locale)
+            .field("attachment_url", &self.attachment_url)
+            .field("image", &self.image)
+            .field("call", &self.call)
+            .field("video", &self.video)
+            .field("audio", &self.audio)
+            .field("thumbnail", &self.thumbnail)
+            .field("push_type", &self.push_type)
+            .field("priority", &self.priority)
+            .field("topic", &self.topic)
+            .field("channel_id", &self.channel_id)
+            .field("urgency", &self.urgency)
+            .finish()
+    }
+}
+
+/// The body of an APNs request, same as [`Msg::serialize`].
+///
+/// # Example
+/// ```rust
+/// use bark_dev::msg::Msg;
+///
+/// let msg = Msg::new("title", "body");
+/// let body: reqwest::Body = (&msg).into();
+/// assert_eq!(body.as_bytes(), Some(msg.serialize().as_bytes()));
+/// ```
+impl From<&Msg> for reqwest::Body {
+    fn from(msg: &Msg) -> Self {
+        reqwest::Body::from(msg.serialize())
+    }
+}
+
+/// The body of an APNs request, same as [`Msg::serialize`].
+impl From<Msg> for reqwest::Body {
+    fn from(msg: Msg) -> Self {
+        reqwest::Body::from(&msg)
+    }
+}
+
+/// Hashes the fields compared by `PartialEq`.
+impl std::hash::Hash for Msg {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        let Msg {
+            title, body, level, badge, auto_copy, copy, sound, icon, group, is_archive, url, iv,
+            enc_type, mode, key, cipher: _, id, is_deleted, launch_image, category, hmac_secret,
+            region, locale, attachment_url, image, call, video, audio, thumbnail, push_type,
+            priority, topic, channel_id, urgency,
+        } = self;
+        title.hash(state);
+        body.hash(state);
+        level.hash(state);
+        badge.hash(state);
+        auto_copy.hash(state);
+        copy.hash(state);
+        sound.hash(state);
+        icon.hash(state);
+        group.hash(state);
+        is_archive.hash(state);
+        url.hash(state);
+        iv.hash(state);
+        enc_type.hash(state);
+        mode.hash(state);
+        key.hash(state);
+        id.hash(state);
+        is_deleted.hash(state);
+        launch_image.hash(state);
+        category.hash(state);
+        hmac_secret.hash(state);
+        region.hash(state);
+        locale.hash(state);
+        attachment_url.hash(state);
+        image.hash(state);
+        call.hash(state);
+        video.hash(state);
+        audio.hash(state);
+        thumbnail.hash(state);
+        push_type.hash(state);
+        priority.hash(state);
+        topic.hash(state);
+        channel_id.hash(state);
+        urgency.hash(state);
+    }
 }
 
 impl Msg {
@@ -237,6 +597,22 @@ impl Msg {
             cipher: None,
             id: None,
             is_deleted: None,
+            launch_image: None,
+            category: DEFAULT_CATEGORY.to_string(),
+            hmac_secret: None,
+            region: None,
+            locale: None,
+            attachment_url: None,
+            image: None,
+            call: None,
+            video: None,
+            audio: None,
+            thumbnail: None,
+            push_type: None,
+            priority: None,
+            topic: None,
+            channel_id: None,
+            urgency: None,
         }
     }
 
@@ -245,10 +621,165 @@ impl Msg {
     }
 
     pub fn is_deleted(&self) -> bool {
-        match self.is_deleted {
-            Some(x) => x == true,
-            None => false
+        self.is_deleted.unwrap_or_default()
+    }
+
+    /// Checks the message can be serialized and sent, without serializing or encrypting it.
+    ///
+    /// Encryption needs the type, mode and key together, the key and IV lengths must match the cipher,
+    /// and deleting a notification needs its id.
+    ///
+    /// # Returns
+    /// `Ok(())` if the message is valid, or every violation found.
+    pub fn is_valid(&self) -> Result<(), Vec<MsgError>> {
+        let mut errors: Vec<MsgError> = Vec::new();
+        let encrypted: bool = self.enc_type.is_some() || self.mode.is_some() || self.key.is_some();
+        if encrypted {
+            if self.enc_type.is_none() {
+                errors.push(MsgError::MissingField("encryption type"));
+            }
+            if self.mode.is_none() {
+                errors.push(MsgError::MissingField("encryption mode"));
+            }
+            if self.key.is_none() {
+                errors.push(MsgError::MissingField("key"));
+            }
+        }
+        if let Some(cipher) = self.cipher {
+            if let Some(key) = &self.key {
+                if key.len() != cipher.key_len() {
+                    errors.push(MsgError::KeyLength { expected: cipher.key_len(), actual: key.len() });
+                }
+            }
+            match (cipher.iv_len(), &self.iv) {
+                (Some(expected), Some(iv)) if self.mode != Some(EncryptMode::ECB) && iv.len() != expected => {
+                    errors.push(MsgError::IvLength { expected, actual: iv.len() });
+                },
+                (Some(_), None) if self.mode != Some(EncryptMode::ECB) => errors.push(MsgError::MissingField("iv")),
+                _ => {},
+            }
         }
+        if let Some(id) = &self.id {
+            if id.len() >= 64 {
+                errors.push(MsgError::IdTooLong(id.len()));
+            }
+        } else if self.is_deleted() {
+            errors.push(MsgError::MissingField("id"));
+        }
+        if errors.is_empty() {
+            return Ok(());
+        }
+        Err(errors)
+    }
+
+    /// Returns the title of the notification.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Returns the content/body of the notification.
+    pub fn body(&self) -> &str {
+        &self.body
+    }
+
+    /// Returns the interruption level as sent to APNS, e.g. `"timeSensitive"`.
+    pub fn level(&self) -> Option<&str> {
+        self.level.map(|level| level.as_str())
+    }
+
+    /// Returns the badge number.
+    pub fn badge(&self) -> Option<u64> {
+        self.badge
+    }
+
+    /// Returns `false` if automatic copy was disabled.
+    pub fn auto_copy(&self) -> bool {
+        self.auto_copy != Some(0)
+    }
+
+    /// Returns the content to copy.
+    pub fn copy(&self) -> Option<&str> {
+        self.copy.as_deref()
+    }
+
+    /// Returns the sound file name.
+    pub fn sound(&self) -> Option<&str> {
+        self.sound.as_deref()
+    }
+
+    /// Returns the custom icon URL.
+    pub fn icon(&self) -> Option<&str> {
+        self.icon.as_deref()
+    }
+
+    /// Returns the group identifier.
+    pub fn group(&self) -> Option<&str> {
+        self.group.as_deref()
+    }
+
+    /// Returns `true` if the notification is saved in the app.
+    pub fn is_archive(&self) -> bool {
+        self.is_archive == Some(1)
+    }
+
+    /// Returns `true` if the notification rings like a phone call.
+    pub fn is_call(&self) -> bool {
+        self.call == Some(1)
+    }
+
+    /// Returns the URL opened when the notification is clicked.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    /// Returns the notification category.
+    pub fn category(&self) -> &str {
+        &self.category
+    }
+
+    /// Returns the launch image.
+    pub fn launch_image(&self) -> Option<&str> {
+        self.launch_image.as_deref()
+    }
+
+    /// Returns the region code.
+    pub fn region(&self) -> Option<&str> {
+        self.region.as_deref()
+    }
+
+    /// Returns the locale.
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Returns the attachment URL.
+    pub fn attachment_url(&self) -> Option<&str> {
+        self.attachment_url.as_deref()
+    }
+
+    /// Returns the image URL.
+    pub fn image_url(&self) -> Option<&str> {
+        self.image.as_deref()
+    }
+
+    /// Returns the initialization vector for encryption.
+    pub fn iv(&self) -> Option<&str> {
+        self.iv.as_deref()
+    }
+
+    /// Returns the encryption type.
+    pub fn enc_type(&self) -> Option<EncryptType> {
+        self.enc_type
+    }
+
+    /// Returns the encryption mode.
+    pub fn mode(&self) -> Option<EncryptMode> {
+        self.mode
+    }
+
+    /// Returns the encryption key.
+    pub fn key(&self) -> Option<&str> {
+        self.key.as_deref()
     }
 
     /// Sets the interruption level of the notification.
@@ -279,6 +810,34 @@ impl Msg {
         self
     }
 
+    /// Sets the badge number to the number of items, e.g. the unread messages.
+    ///
+    /// # Arguments
+    /// - `items`: The items to count, 0 items clears the badge.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_badge_from_count<I: ExactSizeIterator>(&mut self, items: I) -> &mut Self {
+        self.set_badge(items.len() as u64)
+    }
+
+    /// Adjusts the badge number relative to the current one, clamped to 0.
+    ///
+    /// # Arguments
+    /// - `delta`: The amount to add, negative to subtract. A badge that is not set counts as 0.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_badge_increment(&mut self, delta: i64) -> &mut Self {
+        let current: u64 = self.badge.unwrap_or(0);
+        let badge: u64 = if delta < 0 {
+            current.saturating_sub(delta.unsigned_abs())
+        } else {
+            current.saturating_add(delta as u64)
+        };
+        self.set_badge(badge)
+    }
+
     /// Sets whether to automatically copy the notification content.
     ///
     /// # Arguments
@@ -365,6 +924,26 @@ impl Msg {
         self
     }
 
+    /// Sets whether the Bark app rings like a phone call for this notification.
+    ///
+    /// This is a Bark app extension, sent as the top-level `call` key. The ringtone keeps playing
+    /// until the notification is handled, which only works if notifications from Bark are allowed
+    /// to play sounds persistently. Combine it with [`Level::TIMESENSITIVE`] so it also breaks
+    /// through focus modes.
+    ///
+    /// # Arguments
+    /// - `enabled`: true to ring, false for a regular notification.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_call(&mut self, enabled: bool) -> &mut Self {
+        match enabled {
+            true => self.call = Some(1),
+            false => self.call = None,
+        }
+        self
+    }
+
     /// Sets the URL to open when the notification is clicked.
     ///
     /// # Arguments
@@ -381,32 +960,207 @@ impl Msg {
         self
     }
 
-    /// Sets the initialization vector for encryption.
+    /// Sets the URL of a media attachment (image, audio or video) shown with the notification.
+    ///
+    /// APNS has no attachment field, the media is downloaded by a notification service extension
+    /// on the device. This is sent as the top-level `attachmentUrl` key and only takes effect if the
+    /// receiving Bark server and app support it.
+    ///
+    /// # Arguments
+    /// - `url`: An absolute `http` or `https` URL, an empty value removes the attachment.
+    ///   Invalid URLs are ignored and the previous value is kept.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_attachment_url(&mut self, url: &str) -> &mut Self {
+        if url.trim().is_empty() {
+            self.attachment_url = None;
+        } else if let Some(url) = Self::http_url(url) {
+            self.attachment_url = Some(url);
+        }
+        self
+    }
+
+    /// Sets the URL of an image the Bark app shows with the notification.
+    ///
+    /// This is a Bark app extension, not a standard APNS field. It is sent as the top-level
+    /// `image` key and downloaded by the Bark app's notification service extension.
+    ///
+    /// # Arguments
+    /// - `url`: An absolute `http` or `https` URL, an empty value removes the image.
+    ///   Invalid URLs are ignored and the previous value is kept.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_image_url(&mut self, url: &str) -> &mut Self {
+        if url.trim().is_empty() {
+            self.image = None;
+        } else if let Some(url) = Self::http_url(url) {
+            self.image = Some(url);
+        }
+        self
+    }
+
+    /// Sets the media the Bark app shows with the notification.
+    ///
+    /// This is a Bark app extension, not a standard APNS field. The URL is sent as the top-level
+    /// `image`, `video` or `audio` key depending on the [`MediaType`], the thumbnail as `thumbnail`.
+    /// An image replaces the one set with [`Msg::set_image_url`].
+    ///
+    /// # Arguments
+    /// - `content`: The media, with absolute `http` or `https` URLs. If a URL is invalid the
+    ///   content is ignored and the previous media is kept.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_rich_media(&mut self, content: RichMediaContent) -> &mut Self {
+        let Some(url) = Self::http_url(&content.url) else { return self };
+        let thumbnail: Option<String> = match &content.thumbnail_url {
+            Some(thumbnail) => match Self::http_url(thumbnail) {
+                Some(thumbnail) => Some(thumbnail),
+                None => return self,
+            },
+            None => None,
+        };
+        match content.media_type {
+            MediaType::Image => self.image = Some(url),
+            MediaType::Video => self.video = Some(url),
+            MediaType::Audio => self.audio = Some(url),
+        }
+        self.thumbnail = thumbnail;
+        self
+    }
+
+    /// Validates an absolute `http` or `https` URL, logging why it was rejected.
+    fn http_url(url: &str) -> Option<String> {
+        match reqwest::Url::parse(url.trim()) {
+            Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Some(parsed.to_string()),
+            Ok(parsed) => {
+                eprintln!("url {} ignored: unsupported scheme {}", url, parsed.scheme());
+                None
+            },
+            Err(e) => {
+                eprintln!("url {} ignored: {}", url, e);
+                None
+            }
+        }
+    }
+
+    /// Sets the launch image shown while the app launches after tapping the notification.
+    ///
+    /// # Arguments
+    /// - `launch_image`: The file name of an image in the app bundle.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_launch_image(&mut self, launch_image: &str) -> &mut Self {
+        if launch_image.trim().is_empty() {
+            self.launch_image = None;
+        } else {
+            self.launch_image = Some(launch_image.to_string());
+        }
+        self
+    }
+
+    /// Sets the notification category.
+    ///
+    /// The category selects the action buttons (`UNNotificationAction`) shown with the notification.
+    /// The buttons themselves are registered by the app on the device, the push only names the category.
+    /// The Bark app registers `myNotificationCategory` (the default), other identifiers only take effect
+    /// if the receiving app registered them.
+    ///
+    /// # Arguments
+    /// - `category`: The category identifier, an empty value restores the default.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_category(&mut self, category: &str) -> &mut Self {
+        if category.trim().is_empty() {
+            self.category = DEFAULT_CATEGORY.to_string();
+        } else {
+            self.category = category.to_string();
+        }
+        self
+    }
+
+    /// Sets a region code used by server side middleware to filter notifications.
+    ///
+    /// This is not an APNS field, it is sent as the top-level `region` key (outside `aps`)
+    /// and only has an effect with a Bark server that filters on it.
     ///
     /// # Arguments
-    /// - `iv`: The initialization vector.
+    /// - `region_code`: The region code, e.g. `US`.
     ///
     /// # Returns
     /// A mutable reference to `self` for method chaining.
-    pub fn set_iv(&mut self, iv: &str) -> &mut Self {
+    pub fn set_region(&mut self, region_code: &str) -> &mut Self {
+        if region_code.trim().is_empty() {
+            self.region = None;
+        } else {
+            self.region = Some(region_code.trim().to_string());
+        }
+        self
+    }
+
+    /// Sets a locale used by server side middleware to filter notifications.
+    ///
+    /// This is not an APNS field, it is sent as the top-level `locale` key (outside `aps`)
+    /// and only has an effect with a Bark server that filters on it.
+    ///
+    /// # Arguments
+    /// - `locale`: The locale, e.g. `en_US`.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_locale(&mut self, locale: &str) -> &mut Self {
+        if locale.trim().is_empty() {
+            self.locale = None;
+        } else {
+            self.locale = Some(locale.trim().to_string());
+        }
+        self
+    }
+
+    /// Sets the initialization vector for encryption.
+    ///
+    /// # Arguments
+    /// - `iv`: The initialization vector, 16 bytes for [`EncryptMode::CBC`] and 12 bytes for
+    ///   [`EncryptMode::GCM`], empty to clear it. Before the mode is set either length is accepted.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining, or [`MsgError::IvLength`] if the IV does not fit the mode.
+    pub fn set_iv(&mut self, iv: &str) -> Result<&mut Self, MsgError> {
         if iv.trim().is_empty() {
             self.iv = None;
-        } else if iv.len() != 12 {
-            panic!("Invalid IV length. IV must be 12 bytes long.");
+        } else if let Some(expected) = self.iv_len().filter(|expected| iv.len() != *expected) {
+            return Err(MsgError::IvLength { expected, actual: iv.len() });
+        } else if self.iv_len().is_none() && iv.len() != 12 && iv.len() != 16 {
+            return Err(MsgError::IvLength { expected: 12, actual: iv.len() });
         } else {
             self.iv = Some(iv.to_string());
         }
-        self
+        Ok(self)
     }
 
-    /// Generates a random initialization vector.
+    /// Generates a random initialization vector, as long as the mode needs (12 bytes by default).
     ///
     /// # Returns
     /// A mutable reference to `self` for method chaining.
     pub fn gen_iv(&mut self) -> &mut Self {
         let mut iv: [u8; 16] = [0u8; 16];
         openssl::rand::rand_bytes(&mut iv).unwrap();
-        self.set_iv(iv.iter().map(|b| format!("{:02x}", b)).collect::<String>().split_off(16).as_str())
+        let len: usize = self.iv_len().unwrap_or(12);
+        self.iv = Some(iv.iter().map(|b| format!("{:02x}", b)).collect::<String>().split_off(32 - len));
+        self
+    }
+
+    /// The IV length the selected mode needs, `None` before the mode is set or for [`EncryptMode::ECB`].
+    fn iv_len(&self) -> Option<usize> {
+        match self.mode? {
+            EncryptMode::CBC => Some(16),
+            EncryptMode::GCM => Some(12),
+            EncryptMode::ECB => None,
+        }
     }
 
     fn set_cipher(&mut self) -> &mut Self {
@@ -466,18 +1220,15 @@ impl Msg {
     /// # Arguments
     /// - `enc_type`: The encryption type [`EncryptType`].
     ///
-    /// # Panics
-    /// Panics if the encryption type already set.
-    ///
     /// # Returns
-    /// A mutable reference to `self` for method chaining.
-    pub fn set_enc_type(&mut self, enc_type: EncryptType) -> &mut Self {
+    /// A mutable reference to `self` for method chaining, or [`MsgError::EncryptTypeAlreadySet`].
+    pub fn set_enc_type(&mut self, enc_type: EncryptType) -> Result<&mut Self, MsgError> {
         if self.enc_type.is_some() {
-            panic!("Encrypt type can only be set once");
+            return Err(MsgError::EncryptTypeAlreadySet);
         }
         self.enc_type = Some(enc_type);
         self.set_cipher();
-        self
+        Ok(self)
     }
 
     /// Sets the encryption mode and updates the cipher.
@@ -485,49 +1236,58 @@ impl Msg {
     /// # Arguments
     /// - `mode`: The encryption mode [`EncryptMode`].
     ///
-    /// # Panics
-    /// Panics if the encryption mode already set.
-    ///
     /// # Returns
-    /// A mutable reference to `self` for method chaining.
-    pub fn set_mode(&mut self, mode: EncryptMode) -> &mut Self {
+    /// A mutable reference to `self` for method chaining, or [`MsgError::EncryptModeAlreadySet`].
+    pub fn set_mode(&mut self, mode: EncryptMode) -> Result<&mut Self, MsgError> {
         if self.mode.is_some() {
-            panic!("Encrypt mode can only be set once");
+            return Err(MsgError::EncryptModeAlreadySet);
         }
         self.mode = Some(mode);
         match mode {
-            EncryptMode::ECB | EncryptMode::GCM => {
-                if self.iv.is_none() {
-                    self.gen_iv();
-                }
+            EncryptMode::ECB | EncryptMode::GCM if self.iv.is_none() => {
+                self.gen_iv();
             },
             _ => {},
         }
         self.set_cipher();
-        self
+        Ok(self)
     }
 
     /// Sets the encryption key.
     ///
     /// # Arguments
-    /// - `key`: The encryption key.
+    /// - `key`: The encryption key, as long as the encryption type needs (see [`EncryptType::key_len`]).
+    ///   Before the type is set any of 16, 24 or 32 characters is accepted.
     ///
     /// # Returns
-    /// A mutable reference to `self` for method chaining.
-    pub fn set_key(&mut self, key: &str) -> &mut Self {
-        if key.len() != 24 {
-            panic!("Invalid key length. Key must be 24 characters long.");
+    /// A mutable reference to `self` for method chaining, or [`MsgError::KeyLength`] if the key does not fit the type.
+    pub fn set_key(&mut self, key: &str) -> Result<&mut Self, MsgError> {
+        match self.enc_type {
+            Some(enc_type) if key.len() != enc_type.key_len() => {
+                return Err(MsgError::KeyLength { expected: enc_type.key_len(), actual: key.len() });
+            },
+            None if EncryptType::detect_from_key_length(key).is_none() => {
+                return Err(MsgError::KeyLength { expected: 24, actual: key.len() });
+            },
+            _ => {},
         }
         self.key = Some(key.to_string());
-        self
+        Ok(self)
     }
 
-    pub fn set_id(&mut self, msg_id: &str) -> &mut Self {
-        if msg_id.as_bytes().len() >= 64 {
-            panic!("Invalid msg_id length.The value of this key must not exceed 64 bytes.");
+    /// Sets the id, used to update or delete the notification later.
+    ///
+    /// # Arguments
+    /// - `msg_id`: The id, shorter than 64 bytes.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining, or [`MsgError::IdTooLong`].
+    pub fn set_id(&mut self, msg_id: &str) -> Result<&mut Self, MsgError> {
+        if msg_id.len() >= 64 {
+            return Err(MsgError::IdTooLong(msg_id.len()));
         }
         self.id = Some(msg_id.to_string());
-        self
+        Ok(self)
     }
 
     pub fn set_deleted(&mut self) -> &mut Self {
@@ -535,62 +1295,241 @@ impl Msg {
         self
     }
 
-    fn json(&self, encry_body: Option<String>) -> String {
-        let mut body: String = format!("{{\"aps\":{{\"mutable-content\":1,\"category\":\"myNotificationCategory\",\"interruption-level\":\"{level}\",", level = self.level.unwrap_or_else(|| Level::ACTIVE));
+    /// Returns the `apns-push-type` the message is sent with.
+    ///
+    /// Without an explicit push type, deleting a message by id is a background push, anything else an alert.
+    pub fn push_type(&self) -> PushType {
+        match self.push_type {
+            Some(push_type) => push_type,
+            None if self.is_deleted() && self.id.is_some() => PushType::Background,
+            None => PushType::Alert,
+        }
+    }
+
+    /// Sets the `apns-push-type` header.
+    ///
+    /// # Arguments
+    /// - `push_type`: The push type [`PushType`].
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_push_type(&mut self, push_type: PushType) -> &mut Self {
+        self.push_type = Some(push_type);
+        self
+    }
+
+    /// Returns the `apns-priority` the message is sent with, None for the APNS default (10).
+    pub fn priority(&self) -> Option<u8> {
+        self.priority
+    }
+
+    /// Returns the `apns-topic` the message overrides the instance topic with, if any.
+    pub fn topic(&self) -> Option<&str> {
+        self.topic.as_deref()
+    }
+
+    /// Sets the `apns-topic` for this message, e.g. when one instance relays notifications for several apps.
+    ///
+    /// # Arguments
+    /// - `topic`: The bundle id of the receiving app, empty to use the topic of the `Bark` instance.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_topic(&mut self, topic: &str) -> &mut Self {
+        if topic.is_empty() {
+            self.topic = None;
+        } else {
+            self.topic = Some(topic.to_string());
+        }
+        self
+    }
+
+    /// Returns the urgency of the message, None if not set.
+    pub fn urgency(&self) -> Option<NotificationUrgency> {
+        self.urgency
+    }
+
+    /// Sets the urgency, the `apns-priority` header and the Bark `urgency` field, see [`NotificationUrgency`].
+    ///
+    /// # Arguments
+    /// - `urgency`: How urgently the notification should be delivered.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_urgency(&mut self, urgency: NotificationUrgency) -> &mut Self {
+        self.urgency = Some(urgency);
+        self.priority = Some(urgency.priority());
+        self
+    }
+
+    /// Returns the broadcast channel the message is sent to, if any.
+    pub fn channel_id(&self) -> Option<&str> {
+        self.channel_id.as_deref()
+    }
+
+    /// Sets the `apns-channel-id` header, the broadcast channel created for the app, see `Bark::send_to_channel`.
+    ///
+    /// # Arguments
+    /// - `channel`: The base64 channel id returned by APNS, empty to clear it.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_channel_id(&mut self, channel: &str) -> &mut Self {
+        if channel.is_empty() {
+            self.channel_id = None;
+        } else {
+            self.channel_id = Some(channel.to_string());
+        }
+        self
+    }
+
+    /// Sends the message as a Push to Talk notification (iOS 16+).
+    ///
+    /// Sets the push type to [`PushType::PushToTalk`] and the priority to 10, delivered immediately.
+    /// The topic is sent with the `.voip-ptt` suffix. The receiving app needs the Push to Talk
+    /// entitlement (`com.apple.developer.push-to-talk`) and an active channel, Bark itself has neither.
+    ///
+    /// # Arguments
+    /// - `enabled`: true for Push to Talk, false to go back to the derived push type and default priority.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_push_to_talk(&mut self, enabled: bool) -> &mut Self {
+        match enabled {
+            true => {
+                self.push_type = Some(PushType::PushToTalk);
+                self.priority = Some(10);
+            },
+            false => {
+                self.push_type = None;
+                self.priority = None;
+            },
+        }
+        self
+    }
+
+    /// Writes the JSON payload without encryption or signing, character by character.
+    ///
+    /// Nothing is allocated, so the payload can be written into a fixed size buffer.
+    /// The output is the payload [`Msg::serialize`] sends for a message that is neither encrypted,
+    /// signed with [`Msg::sign_payload_hmac`] nor deleting a notification by id.
+    ///
+    /// # Arguments
+    /// - `writer`: Where to write the payload.
+    ///
+    /// # Returns
+    /// An error if the writer fails, e.g. when its buffer is full.
+    pub fn write_json<W: core::fmt::Write>(&self, writer: &mut W) -> core::fmt::Result {
+        self.write_payload(writer, None)
+    }
+
+    fn json(&self, encry_body: Option<(String, Option<String>)>) -> String {
+        let mut body: String = String::new();
+        self.write_payload(&mut body, encry_body.as_ref().map(|(body, tag)| (body.as_str(), tag.as_deref())))
+            .expect("writing to a String never fails");
+        body
+    }
+
+    fn write_payload<W: core::fmt::Write>(&self, body: &mut W, encry_body: Option<(&str, Option<&str>)>) -> core::fmt::Result {
+        write!(body, "{{\"aps\":{{\"mutable-content\":1,\"category\":\"{category}\",\"interruption-level\":\"{level}\",", category = JsonStr(&self.category), level = self.level.unwrap_or(Level::ACTIVE))?;
 
         if let Some(badge) = self.badge {
-            body += &format!("\"badge\":{badge},", badge = badge);
+            write!(body, "\"badge\":{badge},", badge = badge)?;
         }
 
         if let Some(sound) = &self.sound {
-            body += &format!("\"sound\":\"{sound}\",", sound = sound);
+            write!(body, "\"sound\":\"{sound}\",", sound = JsonStr(sound))?;
         }
 
         if let Some(group) = &self.group {
-            body += &format!("\"thread-id\":\"{group}\",", group = group);
+            write!(body, "\"thread-id\":\"{group}\",", group = JsonStr(group))?;
         }
 
-        let alert: String = format!(
-            "\"alert\":{{\"title\":\"{title}\",\"body\":\"{body}\"}}}}",
-            title = self.title,
-            body = if encry_body.is_some() {
+        write!(
+            body,
+            "\"alert\":{{\"title\":\"{title}\",\"body\":\"{body}\"",
+            title = JsonStr(&self.title),
+            body = JsonStr(if encry_body.is_some() {
                 "NoContent"
             } else {
                 self.body.as_str()
-            }
-        );
+            })
+        )?;
+
+        if let Some(launch_image) = &self.launch_image {
+            write!(body, ",\"launch-image\":\"{launch_image}\"", launch_image = JsonStr(launch_image))?;
+        }
 
-        body = body + &alert;
+        body.write_str("}}")?;
 
         if let Some(icon) = &self.icon {
-            body += &format!(",\"icon\":\"{icon}\"", icon = icon);
+            write!(body, ",\"icon\":\"{icon}\"", icon = JsonStr(icon))?;
         }
 
         if let Some(auto_copy) = self.auto_copy {
-            body += &format!(",\"autoCopy\":{auto_copy}", auto_copy = auto_copy);
+            write!(body, ",\"autoCopy\":{auto_copy}", auto_copy = auto_copy)?;
         }
 
         if let Some(is_archive) = self.is_archive {
-            body += &format!(",\"isArchive\":{is_archive}", is_archive = is_archive);
+            write!(body, ",\"isArchive\":{is_archive}", is_archive = is_archive)?;
+        }
+
+        if let Some(call) = self.call {
+            write!(body, ",\"call\":{call}", call = call)?;
+        }
+
+        if let Some(urgency) = self.urgency {
+            write!(body, ",\"urgency\":\"{urgency}\"", urgency = urgency.as_str())?;
         }
 
         if let Some(copy) = &self.copy {
-            body += &format!(",\"copy\":\"{copy}\"", copy = copy);
+            write!(body, ",\"copy\":\"{copy}\"", copy = JsonStr(copy))?;
         }
 
         if let Some(url) = &self.url {
-            body += &format!(",\"url\":\"{url}\"", url = url);
+            write!(body, ",\"url\":\"{url}\"", url = JsonStr(url))?;
+        }
+
+        if let Some(region) = &self.region {
+            write!(body, ",\"region\":\"{region}\"", region = JsonStr(region))?;
+        }
+
+        if let Some(locale) = &self.locale {
+            write!(body, ",\"locale\":\"{locale}\"", locale = JsonStr(locale))?;
+        }
+
+        if let Some(attachment_url) = &self.attachment_url {
+            write!(body, ",\"attachmentUrl\":\"{attachment_url}\"", attachment_url = JsonStr(attachment_url))?;
+        }
+
+        if let Some(image) = &self.image {
+            write!(body, ",\"image\":\"{image}\"", image = JsonStr(image))?;
+        }
+
+        if let Some(video) = &self.video {
+            write!(body, ",\"video\":\"{video}\"", video = JsonStr(video))?;
+        }
+
+        if let Some(audio) = &self.audio {
+            write!(body, ",\"audio\":\"{audio}\"", audio = JsonStr(audio))?;
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            write!(body, ",\"thumbnail\":\"{thumbnail}\"", thumbnail = JsonStr(thumbnail))?;
         }
 
         if let Some(iv) = &self.iv {
-            body += &format!(",\"iv\":\"{iv}\"", iv = iv);
+            write!(body, ",\"iv\":\"{iv}\"", iv = JsonStr(iv))?;
         }
 
-        if let Some(encry_body) = encry_body {
-            body += &format!(",\"ciphertext\":\"{encry_body}\"", encry_body = encry_body);
+        if let Some((encry_body, tag)) = encry_body {
+            write!(body, ",\"ciphertext\":\"{encry_body}\"", encry_body = encry_body)?;
+            if let Some(tag) = tag {
+                write!(body, ",\"tag\":\"{tag}\"", tag = tag)?;
+            }
         }
 
-        body + "}"
+        body.write_str("}")
     }
 
     fn to_json(&self) -> String {
@@ -604,51 +1543,472 @@ impl Msg {
     /// 
     /// # Returns
     /// A `Result` containing the encrypted message as a `String` or an error if the encryption fails.
-    fn encrypt(&self) -> Result<String, Box<dyn std::error::Error>> {
-        if self.enc_type.is_none() || self.mode.is_none() || self.key.is_none() {
-            panic!("Encrypt type, mode, and key must be set");
-        }
+    fn encrypt(&self) -> Result<String, BarkError> {
+        Ok(self.json(Some(self.ciphertext()?)))
+    }
 
-        let key: String = self.key.as_ref().unwrap().clone();
+    /// Encrypts the body and returns the Base64 encoded ciphertext.
+    ///
+    /// # Returns
+    /// The ciphertext and, for [`EncryptMode::GCM`], the Base64 encoded authentication tag.
+    fn ciphertext(&self) -> Result<(String, Option<String>), BarkError> {
+        if self.enc_type.is_none() {
+            return Err(MsgError::MissingField("encryption type").into());
+        }
+        if self.mode.is_none() {
+            return Err(MsgError::MissingField("encryption mode").into());
+        }
+        let key: String = self.key.clone().ok_or(MsgError::MissingField("key"))?;
+        let iv: &str = self.iv.as_deref().ok_or(MsgError::MissingField("iv"))?;
 
-        let original: String = format!("{{\"body\":\"{}\"}}", self.body);
+        let original: String = format!("{{\"body\":\"{}\"}}", JsonStr(&self.body));
         let original: &[u8] = original.as_bytes();
 
-        let cipher: Cipher = self.cipher.unwrap();
-
-        let mut crypter: Crypter = Crypter::new(
-            cipher,
-            Mode::Encrypt,
-            key.as_bytes(),
-            Some(self.iv.as_ref().unwrap().as_bytes()),
-        )
-        .unwrap();
-        crypter.pad(true); // Enable PKCS7 padding
-        let mut buffer: Vec<u8> = vec![0; original.len() + cipher.block_size()];
-        let count: usize = crypter.update(&original, &mut buffer).unwrap();
-        let rest: usize = crypter.finalize(&mut buffer[count..]).unwrap();
+        let cipher: Cipher = self.cipher.ok_or(MsgError::MissingField("encryption type"))?;
+        // openssl panics on a key or IV shorter than the cipher needs, so check them here
+        if key.len() != cipher.key_len() {
+            return Err(MsgError::KeyLength { expected: cipher.key_len(), actual: key.len() }.into());
+        }
+        if let Some(expected) = self.iv_len().filter(|expected| iv.len() != *expected) {
+            return Err(MsgError::IvLength { expected, actual: iv.len() }.into());
+        }
+
+        if self.mode == Some(EncryptMode::GCM) {
+            let (ciphertext, tag) = Self::gcm_encrypt(cipher, key.as_bytes(), iv.as_bytes(), original)
+                .map_err(BarkError::EncryptionFailed)?;
+            return Ok((openssl::base64::encode_block(&ciphertext), Some(openssl::base64::encode_block(&tag))));
+        }
+
+        let encrypt = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+            let mut crypter: Crypter = Crypter::new(cipher, Mode::Encrypt, key.as_bytes(), Some(iv.as_bytes()))?;
+            crypter.pad(true); // Enable PKCS7 padding
+            let mut buffer: Vec<u8> = vec![0; original.len() + cipher.block_size()];
+            let count: usize = crypter.update(original, &mut buffer)?;
+            let rest: usize = crypter.finalize(&mut buffer[count..])?;
+            buffer.truncate(count + rest);
+            Ok(buffer)
+        };
+        let buffer: Vec<u8> = encrypt().map_err(BarkError::EncryptionFailed)?;
+        Ok((openssl::base64::encode_block(&buffer), None))
+    }
+
+    /// Encrypts with AES-GCM.
+    ///
+    /// # Returns
+    /// The ciphertext and the 16 bytes authentication tag, without the tag the app cannot
+    /// authenticate (and so cannot decrypt) the ciphertext.
+    fn gcm_encrypt(cipher: Cipher, key: &[u8], iv: &[u8], plaintext: &[u8]) -> Result<(Vec<u8>, [u8; 16]), openssl::error::ErrorStack> {
+        let mut crypter: Crypter = Crypter::new(cipher, Mode::Encrypt, key, Some(iv))?;
+        let mut buffer: Vec<u8> = vec![0; plaintext.len() + cipher.block_size()];
+        let count: usize = crypter.update(plaintext, &mut buffer)?;
+        let rest: usize = crypter.finalize(&mut buffer[count..])?;
         buffer.truncate(count + rest);
-        Ok(self.json(Some(openssl::base64::encode_block(&buffer))))
+        let mut tag: [u8; 16] = [0u8; 16];
+        crypter.get_tag(&mut tag)?;
+        Ok((buffer, tag))
     }
 
     /// Serializes the message into a JSON string, encrypting the message if necessary.
     /// 
+    /// # Panics
+    /// Panics if the message can not be encrypted, see [`Msg::try_serialize`].
+    ///
     /// # Returns
     /// A `String` containing the serialized message.
     pub fn serialize(&self) -> String {
+        match self.try_serialize() {
+            Ok(payload) => payload,
+            Err(e) => panic!("Error encrypting message: {}", e),
+        }
+    }
+
+    /// Serializes the message into a JSON string, encrypting the message if necessary.
+    ///
+    /// # Returns
+    /// The serialized message, or [`BarkError::InvalidPayload`] / [`BarkError::EncryptionFailed`]
+    /// if the message can not be encrypted.
+    pub fn try_serialize(&self) -> Result<String, BarkError> {
         if let Some(id) = &self.id {
             if self.is_deleted() {
-                return format!("{{\"aps\":{{\"content-available\":1}},\"delete\":\"1\",\"id\":\"{id}\"}}");
+                return self.sign(format!("{{\"aps\":{{\"content-available\":1}},\"delete\":\"1\",\"id\":\"{id}\"}}", id = JsonStr(id)));
             }
         }
         if self.cipher.is_some() {
-            match self.encrypt() {
-                Ok(encrypted) => encrypted,
-                Err(e) => panic!("Error encrypting message: {}", e),
+            self.sign(self.encrypt()?)
+        } else {
+            self.sign(self.to_json())
+        }
+    }
+
+    /// Signs the serialized payload with HMAC-SHA256.
+    ///
+    /// This is not an APNS feature, it is meant for self-hosted Bark servers that verify
+    /// the payload before forwarding it. The signature is computed over the payload without
+    /// the `hmac` key, then appended as the last top-level key: `"hmac":"<lowercase hex>"`.
+    /// To verify, drop the trailing `,"hmac":"..."` and recompute.
+    ///
+    /// # Arguments
+    /// - `secret`: The shared secret.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn sign_payload_hmac(&mut self, secret: &[u8]) -> &mut Self {
+        if secret.is_empty() {
+            self.hmac_secret = None;
+        } else {
+            self.hmac_secret = Some(secret.to_vec());
+        }
+        self
+    }
+
+    /// Appends the HMAC of `payload` if a secret was set.
+    ///
+    /// # Returns
+    /// The signed payload, or [`BarkError::EncryptionFailed`] if openssl could not compute the HMAC.
+    fn sign(&self, payload: String) -> Result<String, BarkError> {
+        let secret: &[u8] = match &self.hmac_secret {
+            Some(secret) => secret,
+            None => return Ok(payload),
+        };
+        let sign = || -> Result<Vec<u8>, openssl::error::ErrorStack> {
+            let key: openssl::pkey::PKey<openssl::pkey::Private> = openssl::pkey::PKey::hmac(secret)?;
+            let mut signer: openssl::sign::Signer<'_> = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
+            signer.update(payload.as_bytes())?;
+            signer.sign_to_vec()
+        };
+        let hmac: String = sign().map_err(BarkError::EncryptionFailed)?.iter().map(|b| format!("{:02x}", b)).collect();
+        Ok(format!("{},\"hmac\":\"{}\"}}", &payload[..payload.len() - 1], hmac))
+    }
+
+    /// Serializes the message into the JSON body of a Bark server `/push` request.
+    ///
+    /// # Arguments
+    /// - `device_key`: The device key registered on the Bark server.
+    ///
+    /// # Returns
+    /// The serialized request body, or [`BarkError::InvalidPayload`] / [`BarkError::EncryptionFailed`]
+    /// if the message can not be encrypted, see [`Msg::try_serialize`].
+    pub(crate) fn serialize_for_server(&self, device_key: &str) -> Result<String, BarkError> {
+        let mut body: String = format!(
+            "{{\"device_key\":\"{device_key}\",\"title\":\"{title}\",\"level\":\"{level}\"",
+            device_key = JsonStr(device_key),
+            title = JsonStr(&self.title),
+            level = self.level.unwrap_or(Level::ACTIVE)
+        );
+
+        if self.cipher.is_some() {
+            let (ciphertext, tag) = self.ciphertext()?;
+            body += &format!(",\"ciphertext\":\"{ciphertext}\"", ciphertext = ciphertext);
+            if let Some(tag) = tag {
+                body += &format!(",\"tag\":\"{tag}\"", tag = tag);
+            }
+            if let Some(iv) = &self.iv {
+                body += &format!(",\"iv\":\"{iv}\"", iv = JsonStr(iv));
             }
         } else {
-            self.to_json()
+            body += &format!(",\"body\":\"{body}\"", body = JsonStr(&self.body));
+        }
+
+        if let Some(badge) = self.badge {
+            body += &format!(",\"badge\":{badge}", badge = badge);
+        }
+
+        if let Some(sound) = &self.sound {
+            body += &format!(",\"sound\":\"{sound}\"", sound = JsonStr(sound));
+        }
+
+        if let Some(icon) = &self.icon {
+            body += &format!(",\"icon\":\"{icon}\"", icon = JsonStr(icon));
+        }
+
+        if let Some(group) = &self.group {
+            body += &format!(",\"group\":\"{group}\"", group = JsonStr(group));
+        }
+
+        if let Some(auto_copy) = self.auto_copy {
+            body += &format!(",\"autoCopy\":\"{auto_copy}\"", auto_copy = auto_copy);
+        }
+
+        if let Some(is_archive) = self.is_archive {
+            body += &format!(",\"isArchive\":\"{is_archive}\"", is_archive = is_archive);
+        }
+
+        if let Some(call) = self.call {
+            body += &format!(",\"call\":\"{call}\"", call = call);
+        }
+
+        if let Some(urgency) = self.urgency {
+            body += &format!(",\"urgency\":\"{urgency}\"", urgency = urgency.as_str());
+        }
+
+        if let Some(copy) = &self.copy {
+            body += &format!(",\"copy\":\"{copy}\"", copy = JsonStr(copy));
+        }
+
+        if let Some(url) = &self.url {
+            body += &format!(",\"url\":\"{url}\"", url = JsonStr(url));
+        }
+
+        if let Some(region) = &self.region {
+            body += &format!(",\"region\":\"{region}\"", region = JsonStr(region));
+        }
+
+        if let Some(locale) = &self.locale {
+            body += &format!(",\"locale\":\"{locale}\"", locale = JsonStr(locale));
+        }
+
+        if let Some(attachment_url) = &self.attachment_url {
+            body += &format!(",\"attachmentUrl\":\"{attachment_url}\"", attachment_url = JsonStr(attachment_url));
+        }
+
+        if let Some(image) = &self.image {
+            body += &format!(",\"image\":\"{image}\"", image = JsonStr(image));
+        }
+
+        if let Some(video) = &self.video {
+            body += &format!(",\"video\":\"{video}\"", video = JsonStr(video));
+        }
+
+        if let Some(audio) = &self.audio {
+            body += &format!(",\"audio\":\"{audio}\"", audio = JsonStr(audio));
+        }
+
+        if let Some(thumbnail) = &self.thumbnail {
+            body += &format!(",\"thumbnail\":\"{thumbnail}\"", thumbnail = JsonStr(thumbnail));
+        }
+
+        if let Some(id) = &self.id {
+            body += &format!(",\"id\":\"{id}\"", id = JsonStr(id));
+            if self.is_deleted() {
+                body += ",\"delete\":\"1\"";
+            }
+        }
+
+        self.sign(body + "}")
+    }
+
+    /// Reads a message from the JSON body of a Bark server `/push` request.
+    ///
+    /// This is the format webhooks and integrations post to a Bark server, e.g.
+    /// `{"device_key":"...","title":"...","body":"...","level":"timeSensitive","badge":1}`, so it
+    /// can be forwarded through APNS instead. Keys are the ones of the push api (`autoCopy`,
+    /// `isArchive`, `attachmentUrl`, ...), flags are `"1"` or `1`. The device keys and keys
+    /// without a message field are ignored. An encrypted `ciphertext` can not be forwarded.
+    ///
+    /// Values go through their setters: media URLs that are not `http` or `https` are ignored
+    /// like [`Msg::set_image_url`] ignores them, and so is a `javascript:`, `file:` or `data:` `url`.
+    ///
+    /// # Arguments
+    /// - `json`: The request body, a flat JSON object.
+    ///
+    /// # Returns
+    /// The message, or [`MsgError::InvalidPayload`] if the JSON or a value is invalid,
+    /// [`MsgError::MissingField`] without a body.
+    pub fn from_bark_webhook(json: &str) -> Result<Msg, MsgError> {
+        let entries: Vec<(String, ConfigValue)> = parse_flat_json(json).map_err(|e| MsgError::InvalidPayload(e.to_string()))?;
+        let text = |key: &str| -> Option<String> {
+            entries.iter().find_map(|(name, value)| match value {
+                ConfigValue::Str(value) if name == key => Some(value.clone()),
+                ConfigValue::Int(value) if name == key => Some(value.to_string()),
+                _ => None,
+            })
+        };
+        if text("ciphertext").is_some() {
+            return Err(MsgError::InvalidPayload("an encrypted ciphertext can not be forwarded".to_string()));
+        }
+        let body: String = text("body").ok_or(MsgError::MissingField("body"))?;
+        let mut msg: Msg = Self::default(text("title"), body);
+        if let Some(level) = text("level") {
+            msg.set_level(Level::from_str(&level).ok_or_else(|| MsgError::InvalidPayload(format!("unknown level {}", level)))?);
+        }
+        if let Some(badge) = text("badge") {
+            msg.set_badge(badge.parse::<u64>().map_err(|_e| MsgError::InvalidPayload(format!("invalid badge {}", badge)))?);
+        }
+        if let Some(urgency) = text("urgency") {
+            let urgency: NotificationUrgency = [
+                NotificationUrgency::VeryLow,
+                NotificationUrgency::Low,
+                NotificationUrgency::Normal,
+                NotificationUrgency::High,
+                NotificationUrgency::Critical,
+            ].into_iter()
+                .find(|known| known.as_str() == urgency)
+                .ok_or_else(|| MsgError::InvalidPayload(format!("unknown urgency {}", urgency)))?;
+            msg.set_urgency(urgency);
+        }
+        let flag = |key: &str| text(key).map(|value| value == "1");
+        if let Some(auto_copy) = flag("autoCopy") {
+            msg.set_auto_copy(auto_copy);
+        }
+        if let Some(is_archive) = flag("isArchive") {
+            msg.set_is_archive(is_archive);
+        }
+        if let Some(call) = flag("call") {
+            msg.set_call(call);
+        }
+        type Setter = for<'a> fn(&'a mut Msg, &str) -> &'a mut Msg;
+        let setters: [(&str, Setter); 8] = [
+            ("sound", Msg::set_sound),
+            ("icon", Msg::set_icon),
+            ("group", Msg::set_group),
+            ("copy", Msg::set_copy),
+            ("region", Msg::set_region),
+            ("locale", Msg::set_locale),
+            ("attachmentUrl", Msg::set_attachment_url),
+            ("image", Msg::set_image_url),
+        ];
+        for (key, setter) in setters {
+            if let Some(value) = text(key) {
+                setter(&mut msg, &value);
+            }
+        }
+        match text("url") {
+            Some(url) if Self::unsafe_url_scheme(&url) => eprintln!("url {} ignored: unsupported scheme", url),
+            Some(url) => {
+                msg.set_url(&url);
+            },
+            None => {},
+        }
+        for (key, media_type) in [("video", MediaType::Video), ("audio", MediaType::Audio)] {
+            if let Some(url) = text(key) {
+                msg.set_rich_media(RichMediaContent { url, thumbnail_url: text("thumbnail"), media_type });
+            }
+        }
+        if let Some(id) = text("id") {
+            msg.set_id(&id)?;
+            if flag("delete") == Some(true) {
+                msg.set_deleted();
+            }
+        }
+        Ok(msg)
+    }
+
+    /// Whether a URL runs code or reads local files when opened, instead of opening a page or an app.
+    fn unsafe_url_scheme(url: &str) -> bool {
+        match reqwest::Url::parse(url.trim()) {
+            Ok(parsed) => matches!(parsed.scheme(), "javascript" | "file" | "data" | "vbscript"),
+            Err(_) => false,
+        }
+    }
+
+    /// Exports the message as a property list in the shape of `UNMutableNotificationContent`.
+    ///
+    /// Useful for rendering the notification on macOS (e.g. with a small test harness
+    /// that loads the plist) without a real APNS connection.
+    /// The body is always exported in plain text, encryption only applies to [`Msg::serialize`].
+    ///
+    /// # Returns
+    /// A `String` containing the property list XML.
+    pub fn to_notification_center_xml(&self) -> String {
+        let mut xml: String = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+            <plist version=\"1.0\">\n<dict>\n"
+        );
+
+        xml += &Self::plist_string("\t", "title", &self.title);
+        xml += &Self::plist_string("\t", "body", &self.body);
+        xml += &Self::plist_string("\t", "categoryIdentifier", &self.category);
+        xml += &Self::plist_string("\t", "interruptionLevel", &self.level.unwrap_or(Level::ACTIVE).to_string());
+
+        if let Some(badge) = self.badge {
+            xml += &format!("\t<key>badge</key>\n\t<integer>{badge}</integer>\n", badge = badge);
+        }
+
+        if let Some(sound) = &self.sound {
+            xml += &Self::plist_string("\t", "sound", sound);
+        }
+
+        if let Some(group) = &self.group {
+            xml += &Self::plist_string("\t", "threadIdentifier", group);
+        }
+
+        xml += "\t<key>userInfo</key>\n\t<dict>\n";
+        if let Some(icon) = &self.icon {
+            xml += &Self::plist_string("\t\t", "icon", icon);
+        }
+        if let Some(auto_copy) = self.auto_copy {
+            xml += &format!("\t\t<key>autoCopy</key>\n\t\t<integer>{auto_copy}</integer>\n", auto_copy = auto_copy);
+        }
+        if let Some(is_archive) = self.is_archive {
+            xml += &format!("\t\t<key>isArchive</key>\n\t\t<integer>{is_archive}</integer>\n", is_archive = is_archive);
+        }
+        if let Some(copy) = &self.copy {
+            xml += &Self::plist_string("\t\t", "copy", copy);
+        }
+        if let Some(url) = &self.url {
+            xml += &Self::plist_string("\t\t", "url", url);
+        }
+        xml += "\t</dict>\n";
+
+        xml + "</dict>\n</plist>\n"
+    }
+
+    /// Draws an ASCII-art approximation of the notification on an iOS lock screen.
+    ///
+    /// The header shows the icon's host name (or a bell when no icon is set) and the app name,
+    /// followed by the title and the body wrapped at 50 characters.
+    /// The app name is always `BARK`, as the topic is configured on [`crate::bark::Bark`], not on the message.
+    ///
+    /// # Returns
+    /// A `String` containing the rendered preview.
+    pub fn render_preview(&self) -> String {
+        const WIDTH: usize = 50;
+        let icon: String = match &self.icon {
+            Some(icon) => reqwest::Url::parse(icon)
+                .ok()
+                .and_then(|url| url.host_str().map(|host| host.to_string()))
+                .unwrap_or_else(|| icon.clone()),
+            None => "\u{1F514}".to_string(),
+        };
+
+        let mut lines: Vec<String> = vec![format!("[{}] BARK", icon), String::new()];
+        lines.extend(Self::wrap(&self.title, WIDTH));
+        lines.extend(Self::wrap(&self.body, WIDTH));
+
+        let border: String = format!("+{}+\n", "-".repeat(WIDTH + 2));
+        let mut preview: String = border.clone();
+        for line in lines {
+            let line: String = line.chars().take(WIDTH).collect();
+            let padding: usize = WIDTH - line.chars().count();
+            preview += &format!("| {}{} |\n", line, " ".repeat(padding));
         }
+        preview + &border
+    }
+
+    /// Greedy word wrap, words longer than `width` are split.
+    fn wrap(text: &str, width: usize) -> Vec<String> {
+        let mut lines: Vec<String> = Vec::new();
+        for paragraph in text.lines() {
+            let mut line: String = String::new();
+            for word in paragraph.split_whitespace() {
+                let mut word: Vec<char> = word.chars().collect();
+                while word.len() > width {
+                    if !line.is_empty() {
+                        lines.push(std::mem::take(&mut line));
+                    }
+                    lines.push(word.drain(..width).collect());
+                }
+                let word: String = word.into_iter().collect();
+                if line.is_empty() {
+                    line = word;
+                } else if line.chars().count() + 1 + word.chars().count() <= width {
+                    line = line + " " + &word;
+                } else {
+                    lines.push(std::mem::replace(&mut line, word));
+                }
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    fn plist_string(indent: &str, key: &str, value: &str) -> String {
+        let value: String = value
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        format!("{indent}<key>{key}</key>\n{indent}<string>{value}</string>\n", indent = indent, key = key, value = value)
     }
 }
 
@@ -694,4 +2054,484 @@ mod tests {
         println!("{}", json);
         assert_eq!(json, "{\"aps\":{\"mutable-content\":1,\"category\":\"myNotificationCategory\",\"interruption-level\":\"active\",\"sound\":\"chime.caf\",\"alert\":{\"title\":\"Test Title\",\"body\":\"Test Body\"}},\"icon\":\"https://github.com/66f94eae/bark-dev/raw/main/bot.jpg\"}");
     }
-}
+
+    #[test]
+    fn test_to_notification_center_xml() {
+        let mut msg = Msg::new("Test <Title>", "Test & Body");
+        msg.set_badge(2);
+        msg.set_group("Test Group");
+        msg.set_url("https://example.com");
+        msg.set_copy("Test Copy");
+        msg.set_icon("https://example.com/icon.png");
+        let xml = msg.to_notification_center_xml();
+        println!("{}", xml);
+        assert!(xml.contains("\t<key>title</key>\n\t<string>Test &lt;Title&gt;</string>\n"));
+        assert!(xml.contains("\t<key>body</key>\n\t<string>Test &amp; Body</string>\n"));
+        assert!(xml.contains("\t<key>badge</key>\n\t<integer>2</integer>\n"));
+        assert!(xml.contains("\t<key>threadIdentifier</key>\n\t<string>Test Group</string>\n"));
+        assert!(xml.contains("\t\t<key>url</key>\n\t\t<string>https://example.com</string>\n"));
+        assert!(xml.contains("\t\t<key>copy</key>\n\t\t<string>Test Copy</string>\n"));
+        assert!(xml.contains("\t\t<key>icon</key>\n\t\t<string>https://example.com/icon.png</string>\n"));
+        assert!(xml.ends_with("</dict>\n</plist>\n"));
+    }
+
+    #[test]
+    fn test_getters() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.title(), "Test Title");
+        assert_eq!(msg.body(), "Test Body");
+        assert_eq!(msg.level(), None);
+        assert!(msg.auto_copy());
+        assert!(!msg.is_archive());
+        assert_eq!(msg.sound(), Some("chime.caf"));
+
+        msg.set_level(Level::TIMESENSITIVE);
+        msg.set_auto_copy(false);
+        msg.set_is_archive(true);
+        msg.set_group("Test Group");
+        msg.set_enc_type(EncryptType::AES192).unwrap();
+        msg.set_mode(EncryptMode::CBC).unwrap();
+        msg.set_key("123456789012345678901234").unwrap();
+        assert_eq!(msg.level(), Some("timeSensitive"));
+        assert!(!msg.auto_copy());
+        assert!(msg.is_archive());
+        assert_eq!(msg.group(), Some("Test Group"));
+        assert_eq!(msg.enc_type(), Some(EncryptType::AES192));
+        assert_eq!(msg.mode(), Some(EncryptMode::CBC));
+        assert_eq!(msg.key(), Some("123456789012345678901234"));
+        assert_eq!(msg.iv(), None);
+    }
+
+    #[test]
+    fn test_to_json_launch_image() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_launch_image("launch.png");
+        let json = msg.to_json();
+        println!("{}", json);
+        assert_eq!(json, "{\"aps\":{\"mutable-content\":1,\"category\":\"myNotificationCategory\",\"interruption-level\":\"active\",\"sound\":\"chime.caf\",\"alert\":{\"title\":\"Test Title\",\"body\":\"Test Body\",\"launch-image\":\"launch.png\"}},\"icon\":\"https://github.com/66f94eae/bark-dev/raw/main/bot.jpg\"}");
+    }
+
+    #[test]
+    fn test_render_preview() {
+        let msg = Msg::new("Test Title", "a fairly long body that has to be wrapped because it does not fit on one line");
+        let preview = msg.render_preview();
+        println!("{}", preview);
+        let lines: Vec<&str> = preview.lines().collect();
+        assert_eq!(lines[0], format!("+{}+", "-".repeat(52)));
+        assert!(lines[1].starts_with("| [github.com] BARK "));
+        assert!(lines[3].starts_with("| Test Title "));
+        assert_eq!(lines[4], "| a fairly long body that has to be wrapped because  |");
+        assert!(lines[5].starts_with("| it does not fit on one line "));
+        assert!(lines.iter().all(|line| line.chars().count() == 54));
+    }
+
+    #[test]
+    fn test_detect_from_key_length() {
+        assert_eq!(EncryptType::detect_from_key_length("1234567890123456"), Some(EncryptType::AES128));
+        assert_eq!(EncryptType::detect_from_key_length("123456789012345678901234"), Some(EncryptType::AES192));
+        assert_eq!(EncryptType::detect_from_key_length("12345678901234567890123456789012"), Some(EncryptType::AES256));
+        assert_eq!(EncryptType::detect_from_key_length(""), None);
+        assert_eq!(EncryptType::detect_from_key_length("12345678"), None);
+
+        for key in ["1234567890123456", "123456789012345678901234", "12345678901234567890123456789012"] {
+            let enc_type: EncryptType = EncryptType::detect_from_key_length(key).unwrap();
+            assert_eq!(enc_type.key_len(), key.len());
+            let mut msg = Msg::new("Test Title", "Test Body");
+            msg.set_enc_type(enc_type).unwrap().set_mode(EncryptMode::GCM).unwrap().set_key(key).unwrap();
+            assert!(msg.try_serialize().is_ok(), "{:?}", enc_type);
+        }
+    }
+
+    #[test]
+    fn test_to_json_category() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.category(), "myNotificationCategory");
+        msg.set_category("customCategory");
+        assert!(msg.to_json().contains("\"category\":\"customCategory\""));
+        msg.set_category("");
+        assert_eq!(msg.category(), "myNotificationCategory");
+    }
+
+    #[test]
+    fn test_sign_payload_hmac() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        let unsigned = msg.serialize();
+        msg.sign_payload_hmac(b"key");
+        let signed = msg.serialize();
+        println!("{}", signed);
+        // echo -n '<unsigned>' | openssl dgst -sha256 -hmac key
+        assert_eq!(signed, format!("{},\"hmac\":\"{}\"}}", &unsigned[..unsigned.len() - 1], "24c0445199b7f2d57f6c0cf3e091e0aa3d9f593e46aa6193c560c9501c4f7763"));
+    }
+
+    #[test]
+    fn test_to_json_region_locale() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_region("US");
+        msg.set_locale("en_US");
+        let json = msg.to_json();
+        println!("{}", json);
+        assert!(json.ends_with("}},\"region\":\"US\",\"locale\":\"en_US\"}"));
+    }
+
+    #[test]
+    fn test_attachment_url() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_attachment_url("ftp://example.com/a.png");
+        assert_eq!(msg.attachment_url(), None);
+        msg.set_attachment_url("https://example.com/a.png");
+        msg.set_attachment_url("not a url");
+        assert_eq!(msg.attachment_url(), Some("https://example.com/a.png"));
+        assert!(msg.to_json().ends_with("}},\"attachmentUrl\":\"https://example.com/a.png\"}"));
+    }
+
+    #[test]
+    fn test_image_url() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_image_url("example.com/a.png");
+        assert_eq!(msg.image_url(), None);
+        msg.set_image_url("https://example.com/a.png");
+        assert!(msg.to_json().ends_with("}},\"image\":\"https://example.com/a.png\"}"));
+        msg.set_image_url("");
+        assert_eq!(msg.image_url(), None);
+    }
+
+    #[test]
+    fn test_gcm_tag() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_enc_type(EncryptType::AES192).unwrap();
+        msg.set_mode(EncryptMode::GCM).unwrap();
+        msg.set_key("123456789012345678901234").unwrap();
+        msg.set_iv("123456789012").unwrap();
+        let (ciphertext, tag) = msg.ciphertext().unwrap();
+        let tag = openssl::base64::decode_block(&tag.unwrap()).unwrap();
+        let plain = openssl::symm::decrypt_aead(
+            Cipher::aes_192_gcm(),
+            b"123456789012345678901234",
+            Some(b"123456789012"),
+            &[],
+            &openssl::base64::decode_block(&ciphertext).unwrap(),
+            &tag,
+        ).unwrap();
+        assert_eq!(plain, b"{\"body\":\"Test Body\"}");
+        assert!(msg.serialize().contains(",\"tag\":\""));
+    }
+
+    #[test]
+    fn test_cbc_iv_length() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_enc_type(EncryptType::AES192).unwrap().set_mode(EncryptMode::CBC).unwrap();
+        msg.set_key("123456789012345678901234").unwrap();
+        assert_eq!(msg.set_iv("123456789012").err(), Some(MsgError::IvLength { expected: 16, actual: 12 }));
+        msg.set_iv("1234567890123456").unwrap();
+        let (ciphertext, tag) = msg.ciphertext().unwrap();
+        assert!(tag.is_none());
+        let plain = openssl::symm::decrypt(
+            Cipher::aes_192_cbc(),
+            b"123456789012345678901234",
+            Some(b"1234567890123456"),
+            &openssl::base64::decode_block(&ciphertext).unwrap(),
+        ).unwrap();
+        assert_eq!(plain, b"{\"body\":\"Test Body\"}");
+
+        // the IV set before the mode is checked when encrypting instead of panicking in openssl
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_iv("123456789012").unwrap().set_key("1234567890123456").unwrap();
+        msg.set_enc_type(EncryptType::AES192).unwrap().set_mode(EncryptMode::CBC).unwrap();
+        let error = msg.try_serialize().unwrap_err().to_string();
+        assert!(error.contains("key must be 24 bytes, got 16"), "{}", error);
+        msg.key = Some("123456789012345678901234".to_string());
+        let error = msg.try_serialize().unwrap_err().to_string();
+        assert!(error.contains("iv must be 16 bytes, got 12"), "{}", error);
+        msg.gen_iv();
+        assert_eq!(msg.iv().map(str::len), Some(16));
+        assert!(msg.try_serialize().is_ok());
+    }
+
+    #[test]
+    fn test_eq() {
+        let mut first = Msg::new("Test Title", "Test Body");
+        let mut second = first.clone();
+        assert_eq!(first, second);
+        second.set_sound("bell.caf");
+        assert_ne!(first, second);
+        first.set_sound("bell.caf");
+        assert_eq!(first, second);
+
+        let set: std::collections::HashSet<Msg> = [first, second].into_iter().collect();
+        assert_eq!(set.len(), 1);
+    }
+
+    #[test]
+    fn test_eq_every_field() {
+        fn hash_of(msg: &Msg) -> u64 {
+            use std::hash::{Hash, Hasher};
+            let mut hasher: std::collections::hash_map::DefaultHasher = Default::default();
+            msg.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        type Change = (&'static str, fn(&mut Msg));
+
+        let base: Msg = Msg::new("Test Title", "Test Body");
+        let changes: [Change; 33] = [
+            ("title", |msg| msg.title = "other".to_string()),
+            ("body", |msg| msg.body = "other".to_string()),
+            ("level", |msg| msg.level = Some(Level::PASSIVE)),
+            ("badge", |msg| msg.badge = Some(1)),
+            ("auto_copy", |msg| msg.auto_copy = Some(1)),
+            ("copy", |msg| msg.copy = Some("other".to_string())),
+            ("sound", |msg| msg.sound = Some("other".to_string())),
+            ("icon", |msg| msg.icon = Some("other".to_string())),
+            ("group", |msg| msg.group = Some("other".to_string())),
+            ("is_archive", |msg| msg.is_archive = Some(0)),
+            ("url", |msg| msg.url = Some("other".to_string())),
+            ("iv", |msg| msg.iv = Some("other".to_string())),
+            ("enc_type", |msg| msg.enc_type = Some(EncryptType::AES256)),
+            ("mode", |msg| msg.mode = Some(EncryptMode::GCM)),
+            ("key", |msg| msg.key = Some("other".to_string())),
+            ("id", |msg| msg.id = Some("other".to_string())),
+            ("is_deleted", |msg| msg.is_deleted = Some(true)),
+            ("launch_image", |msg| msg.launch_image = Some("other".to_string())),
+            ("category", |msg| msg.category = "other".to_string()),
+            ("hmac_secret", |msg| msg.hmac_secret = Some(b"other".to_vec())),
+            ("region", |msg| msg.region = Some("other".to_string())),
+            ("locale", |msg| msg.locale = Some("other".to_string())),
+            ("attachment_url", |msg| msg.attachment_url = Some("other".to_string())),
+            ("image", |msg| msg.image = Some("other".to_string())),
+            ("call", |msg| msg.call = Some(1)),
+            ("video", |msg| msg.video = Some("other".to_string())),
+            ("audio", |msg| msg.audio = Some("other".to_string())),
+            ("thumbnail", |msg| msg.thumbnail = Some("other".to_string())),
+            ("push_type", |msg| msg.push_type = Some(PushType::Background)),
+            ("priority", |msg| msg.priority = Some(1)),
+            ("topic", |msg| msg.topic = Some("other".to_string())),
+            ("channel_id", |msg| msg.channel_id = Some("other".to_string())),
+            ("urgency", |msg| msg.urgency = Some(NotificationUrgency::Critical)),
+        ];
+        for (field, change) in changes {
+            let mut changed: Msg = base.clone();
+            change(&mut changed);
+            assert_ne!(base, changed, "{} is not compared", field);
+            assert_ne!(hash_of(&base), hash_of(&changed), "{} is not hashed", field);
+        }
+
+        // the cipher follows from the encryption type and is left out
+        let mut changed: Msg = base.clone();
+        changed.cipher = Some(Cipher::aes_256_gcm());
+        assert_eq!(base, changed);
+        assert_eq!(hash_of(&base), hash_of(&changed));
+    }
+
+    #[test]
+    fn test_push_type() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.push_type(), PushType::Alert);
+        msg.set_push_to_talk(true);
+        assert_eq!(msg.push_type().as_str(), "voip-ptt");
+        assert_eq!(msg.priority(), Some(10));
+        msg.set_push_to_talk(false);
+        msg.set_id("id").unwrap().set_deleted();
+        assert_eq!(msg.push_type(), PushType::Background);
+    }
+
+    #[test]
+    fn test_topic() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.topic(), None);
+        msg.set_topic("com.example.relay");
+        assert_eq!(msg.topic(), Some("com.example.relay"));
+        assert!(msg != Msg::new("Test Title", "Test Body"));
+        msg.set_topic("");
+        assert_eq!(msg.topic(), None);
+    }
+
+    #[test]
+    fn test_channel_id() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.channel_id(), None);
+        msg.set_channel_id("dHN0LXNyY2gtY2hubA==");
+        assert_eq!(msg.channel_id(), Some("dHN0LXNyY2gtY2hubA=="));
+        msg.set_channel_id("");
+        assert_eq!(msg.channel_id(), None);
+    }
+
+    #[test]
+    fn test_rich_media() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_rich_media(RichMediaContent {
+            url: "https://example.com/a.mp4".to_string(),
+            thumbnail_url: Some("not a url".to_string()),
+            media_type: MediaType::Video,
+        });
+        assert!(!msg.to_json().contains("video"));
+        msg.set_rich_media(RichMediaContent {
+            url: "https://example.com/a.mp4".to_string(),
+            thumbnail_url: Some("https://example.com/a.jpg".to_string()),
+            media_type: MediaType::Video,
+        });
+        assert!(msg.to_json().ends_with("}},\"video\":\"https://example.com/a.mp4\",\"thumbnail\":\"https://example.com/a.jpg\"}"));
+    }
+
+    #[test]
+    fn test_call() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_call(true);
+        assert!(msg.is_call());
+        assert!(msg.to_json().ends_with("}},\"call\":1}"));
+        msg.set_call(false);
+        assert!(!msg.to_json().contains("call"));
+    }
+
+    #[test]
+    fn test_badge_count() {
+        let mut msg = Msg::new("title", "body");
+        let unread = ["a", "b", "c"];
+        msg.set_badge_from_count(unread.iter());
+        assert_eq!(msg.badge(), Some(3));
+        msg.set_badge_increment(2);
+        assert_eq!(msg.badge(), Some(5));
+        msg.set_badge_increment(-10);
+        assert_eq!(msg.badge(), None);
+        msg.set_badge_increment(1);
+        assert_eq!(msg.badge(), Some(1));
+    }
+
+    #[test]
+    fn test_write_json() {
+        struct Buffer {
+            bytes: [u8; 512],
+            len: usize,
+        }
+        impl core::fmt::Write for Buffer {
+            fn write_str(&mut self, s: &str) -> core::fmt::Result {
+                let end = self.len + s.len();
+                if end > self.bytes.len() {
+                    return Err(core::fmt::Error);
+                }
+                self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+                self.len = end;
+                Ok(())
+            }
+        }
+
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_badge(2).set_url("https://example.com");
+        let mut buffer = Buffer { bytes: [0u8; 512], len: 0 };
+        msg.write_json(&mut buffer).unwrap();
+        assert_eq!(core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap(), msg.serialize());
+
+        let msg = Msg::new("Test Title", &"x".repeat(512));
+        let mut buffer = Buffer { bytes: [0u8; 512], len: 0 };
+        assert!(msg.write_json(&mut buffer).is_err());
+    }
+
+    #[test]
+    fn test_is_valid() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.is_valid(), Ok(()));
+        msg.set_key("123456789012345678901234").unwrap();
+        assert_eq!(msg.is_valid(), Err(vec![MsgError::MissingField("encryption type"), MsgError::MissingField("encryption mode")]));
+        msg.set_enc_type(EncryptType::AES192).unwrap().set_mode(EncryptMode::GCM).unwrap();
+        assert_eq!(msg.is_valid(), Ok(()));
+
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_key("123456789012345678901234").unwrap().set_iv("123456789012").unwrap();
+        msg.set_enc_type(EncryptType::AES256).unwrap().set_mode(EncryptMode::CBC).unwrap();
+        msg.set_deleted();
+        assert_eq!(msg.is_valid(), Err(vec![
+            MsgError::KeyLength { expected: 32, actual: 24 },
+            MsgError::IvLength { expected: 16, actual: 12 },
+            MsgError::MissingField("id"),
+        ]));
+        assert_eq!(MsgError::IvLength { expected: 16, actual: 12 }.to_string(), "iv must be 16 bytes, got 12");
+
+        let mut msg = Msg::new("Test Title", "Test Body");
+        assert_eq!(msg.set_iv("1234").err(), Some(MsgError::IvLength { expected: 12, actual: 4 }));
+        assert_eq!(msg.set_key("1234").err(), Some(MsgError::KeyLength { expected: 24, actual: 4 }));
+        assert!(msg.set_enc_type(EncryptType::AES128).is_ok());
+        assert_eq!(msg.set_enc_type(EncryptType::AES256).err(), Some(MsgError::EncryptTypeAlreadySet));
+        assert!(msg.set_mode(EncryptMode::CBC).is_ok());
+        assert_eq!(msg.set_mode(EncryptMode::GCM).err(), Some(MsgError::EncryptModeAlreadySet));
+        assert!(matches!(msg.try_serialize(), Err(BarkError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_urgency() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_icon("");
+        msg.set_urgency(NotificationUrgency::Low);
+        assert_eq!(msg.urgency(), Some(NotificationUrgency::Low));
+        assert_eq!(msg.priority(), Some(5));
+        assert!(msg.to_json().ends_with(",\"urgency\":\"low\"}"));
+        assert!(msg.serialize_for_server("key").unwrap().contains(",\"urgency\":\"low\""));
+        msg.set_urgency(NotificationUrgency::VeryLow);
+        assert_eq!(msg.priority(), Some(1));
+        msg.set_urgency(NotificationUrgency::Critical);
+        assert_eq!(msg.priority(), Some(10));
+    }
+
+    #[test]
+    fn test_from_bark_webhook() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_level(Level::TIMESENSITIVE);
+        msg.set_badge(3);
+        msg.set_group("group");
+        msg.set_auto_copy(false);
+        msg.set_is_archive(true);
+        msg.set_call(true);
+        msg.set_urgency(NotificationUrgency::High);
+        msg.set_copy("copy");
+        msg.set_url("https://example.com");
+        msg.set_id("id").unwrap();
+        assert_eq!(Msg::from_bark_webhook(&msg.serialize_for_server("key").unwrap()).unwrap(), msg);
+
+        let msg = Msg::from_bark_webhook("{\"device_key\": \"key\", \"body\": \"hello\", \"badge\": 1, \"isArchive\": 1}").unwrap();
+        assert_eq!(msg.body, "hello");
+        assert_eq!(msg.badge, Some(1));
+        assert_eq!(msg.is_archive, Some(1));
+
+        assert!(matches!(Msg::from_bark_webhook("{\"title\": \"title\"}"), Err(MsgError::MissingField("body"))));
+        assert!(matches!(Msg::from_bark_webhook("{\"body\": \"body\", \"level\": \"loud\"}"), Err(MsgError::InvalidPayload(_))));
+        assert!(matches!(Msg::from_bark_webhook("{\"ciphertext\": \"abc\", \"body\": \"body\"}"), Err(MsgError::InvalidPayload(_))));
+        assert!(matches!(Msg::from_bark_webhook("not json"), Err(MsgError::InvalidPayload(_))));
+    }
+
+    #[test]
+    fn test_escape_strings() {
+        let mut msg = Msg::new("say \"hi\"\n", "back\\slash\t\u{1}");
+        msg.set_level(Level::ACTIVE).set_group("a\"b").set_copy("\"},\"x\":\"");
+        let payload = msg.serialize();
+        assert!(payload.contains("\"alert\":{\"title\":\"say \\\"hi\\\"\\n\",\"body\":\"back\\\\slash\\t\\u0001\"}"));
+        assert!(payload.contains(",\"copy\":\"\\\"},\\\"x\\\":\\\"\""));
+        assert_eq!(Msg::from_bark_webhook(&msg.serialize_for_server("key").unwrap()).unwrap(), msg);
+
+        let msg = Msg::from_bark_webhook("{\"body\": \"x\\\",\\\"copy\\\":\\\"injected\"}").unwrap();
+        assert_eq!(msg.body, "x\",\"copy\":\"injected");
+        assert_eq!(msg.copy, None);
+        assert!(!msg.serialize().contains(",\"copy\":\"injected\""));
+    }
+
+    #[test]
+    fn test_from_bark_webhook_urls() {
+        let json = concat!(
+            "{\"body\": \"\\ud83d\\ude00\", \"url\": \"javascript:alert(1)\", \"image\": \"file:///etc/passwd\",",
+            " \"attachmentUrl\": \"https://example.com/a.png\", \"video\": \"https://example.com/v.mp4\",",
+            " \"thumbnail\": \"https://example.com/t.png\", \"audio\": \"ftp://example.com/a.mp3\"}"
+        );
+        let msg = Msg::from_bark_webhook(json).unwrap();
+        assert_eq!(msg.body, "\u{1F600}");
+        assert_eq!(msg.url, None);
+        assert_eq!(msg.image, None);
+        assert_eq!(msg.audio, None);
+        assert_eq!(msg.attachment_url.as_deref(), Some("https://example.com/a.png"));
+        assert_eq!(msg.video.as_deref(), Some("https://example.com/v.mp4"));
+        assert_eq!(msg.thumbnail.as_deref(), Some("https://example.com/t.png"));
+        let msg = Msg::from_bark_webhook("{\"body\": \"body\", \"url\": \"weixin://\"}").unwrap();
+        assert_eq!(msg.url.as_deref(), Some("weixin://"));
+    }
+}
\ No newline at end of file
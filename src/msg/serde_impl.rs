@@ -0,0 +1,276 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+//! `Serialize` and `Deserialize` for [`Msg`] and its enums, behind the `serde` feature
+//!
+//! enums are written as the strings bark and apns use (`timeSensitive`, `voip-ptt`, `aes128`, ...),
+//! the cipher is not written, it is derived again from the encryption type and mode.
+//!
+//! **The encryption `key` and the `hmac_secret` are written in clear text**, so that a message
+//! read back still encrypts and signs the same way. Anything holding the output holds the secrets.
+//!
+//! Missing fields keep the defaults of [`Msg::new`], unknown fields are ignored.
+//! A deserialized message is checked like the setters check it, see [`Msg::is_valid`],
+//! media URLs must be `http` or `https` and `url` must not run code or read local files.
+
+use std::fmt::Formatter;
+
+use ::serde::{de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor}, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
+
+use super::{EncryptMode, EncryptType, Level, Msg, NotificationUrgency, PushType};
+
+/// (de)serialize the listed fields of `Msg`, in this order for formats without field names
+macro_rules! msg_fields {
+    ($($field:ident),* $(,)?) => {
+        const FIELDS: &[&str] = &[$(stringify!($field)),*];
+
+        impl Serialize for Msg {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                let mut state = serializer.serialize_struct("Msg", FIELDS.len())?;
+                $(state.serialize_field(stringify!($field), &self.$field)?;)*
+                state.end()
+            }
+        }
+
+        impl<'de> Visitor<'de> for MsgVisitor {
+            type Value = Msg;
+
+            fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+                f.write_str("a bark message")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Msg, A::Error> {
+                let mut msg: Msg = Msg::new("", "");
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        $(stringify!($field) => msg.$field = map.next_value()?,)*
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        },
+                    }
+                }
+                msg.set_cipher();
+                check(&msg).map_err(de::Error::custom)?;
+                Ok(msg)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Msg, A::Error> {
+                let mut msg: Msg = Msg::new("", "");
+                let mut index: usize = 0;
+                $(
+                    msg.$field = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(index, &self))?;
+                    index += 1;
+                )*
+                let _ = index;
+                msg.set_cipher();
+                check(&msg).map_err(de::Error::custom)?;
+                Ok(msg)
+            }
+        }
+    };
+}
+
+struct MsgVisitor;
+
+/// the checks the setters do, which deserializing into the fields directly skips
+fn check(msg: &Msg) -> Result<(), String> {
+    if let Err(errors) = msg.is_valid() {
+        return Err(errors.iter().map(|e| e.to_string()).collect::<Vec<String>>().join(", "));
+    }
+    let media = [
+        ("attachment_url", &msg.attachment_url),
+        ("image", &msg.image),
+        ("video", &msg.video),
+        ("audio", &msg.audio),
+        ("thumbnail", &msg.thumbnail),
+    ];
+    for (field, url) in media {
+        let Some(url) = url else { continue };
+        match reqwest::Url::parse(url.trim()) {
+            Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {},
+            Ok(parsed) => return Err(format!("{}: unsupported scheme {}", field, parsed.scheme())),
+            Err(e) => return Err(format!("{}: {}", field, e)),
+        }
+    }
+    match &msg.url {
+        Some(url) if Msg::unsafe_url_scheme(url) => Err(format!("url: unsupported scheme in {}", url)),
+        _ => Ok(()),
+    }
+}
+
+msg_fields!(
+    title, body, level, badge, auto_copy, copy, sound, icon, group, is_archive, url,
+    iv, enc_type, mode, key, id, is_deleted, launch_image, category, hmac_secret,
+    region, locale, attachment_url, image, call, video, audio, thumbnail,
+    push_type, priority, topic, channel_id, urgency,
+);
+
+impl<'de> Deserialize<'de> for Msg {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_struct("Msg", FIELDS, MsgVisitor)
+    }
+}
+
+/// reads an enum written as one of its names
+struct NameVisitor<T> {
+    expected: &'static str,
+    parse: fn(&str) -> Option<T>,
+}
+
+impl<T> Visitor<'_> for NameVisitor<T> {
+    type Value = T;
+
+    fn expecting(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.expected)
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<T, E> {
+        (self.parse)(value).ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+fn deserialize_name<'de, D: Deserializer<'de>, T>(deserializer: D, expected: &'static str, parse: fn(&str) -> Option<T>) -> Result<T, D::Error> {
+    deserializer.deserialize_str(NameVisitor { expected, parse })
+}
+
+impl Serialize for Level {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Level {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_name(deserializer, "active, timeSensitive or passive", Level::from_str)
+    }
+}
+
+impl Serialize for EncryptType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            EncryptType::AES128 => "aes128",
+            EncryptType::AES192 => "aes192",
+            EncryptType::AES256 => "aes256",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_name(deserializer, "aes128, aes192 or aes256", EncryptType::from_str)
+    }
+}
+
+impl Serialize for EncryptMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match self {
+            EncryptMode::CBC => "cbc",
+            EncryptMode::ECB => "ecb",
+            EncryptMode::GCM => "gcm",
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for EncryptMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_name(deserializer, "cbc, ecb or gcm", EncryptMode::from_str)
+    }
+}
+
+impl Serialize for PushType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for PushType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_name(deserializer, "alert, background or voip-ptt", |str| {
+            [PushType::Alert, PushType::Background, PushType::PushToTalk].into_iter().find(|push_type| push_type.as_str() == str)
+        })
+    }
+}
+
+impl Serialize for NotificationUrgency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for NotificationUrgency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_name(deserializer, "veryLow, low, normal, high or critical", |str| {
+            [
+                NotificationUrgency::VeryLow,
+                NotificationUrgency::Low,
+                NotificationUrgency::Normal,
+                NotificationUrgency::High,
+                NotificationUrgency::Critical,
+            ].into_iter().find(|urgency| urgency.as_str() == str)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let mut msg = Msg::new("Test Title", "Test Body");
+        msg.set_level(Level::TIMESENSITIVE)
+            .set_badge(3)
+            .set_push_type(PushType::Background)
+            .set_urgency(NotificationUrgency::VeryLow);
+        msg.set_enc_type(EncryptType::AES192).unwrap()
+            .set_mode(EncryptMode::GCM).unwrap()
+            .set_key("123456789012345678901234").unwrap()
+            .set_iv("123456789012").unwrap();
+
+        let encoded: String = serde_urlencoded::to_string(&msg).unwrap();
+        assert!(encoded.contains("level=timeSensitive"));
+        assert!(encoded.contains("enc_type=aes192"));
+        assert!(encoded.contains("push_type=background"));
+        let decoded: Msg = serde_urlencoded::from_str(&encoded).unwrap();
+        assert_eq!(decoded, msg);
+        assert_eq!(decoded.serialize(), msg.serialize());
+
+        let decoded: Msg = serde_urlencoded::from_str("title=Hi&unknown=1&urgency=high").unwrap();
+        assert_eq!(decoded.urgency(), Some(NotificationUrgency::High));
+        assert_eq!(decoded.category, Msg::new("", "").category);
+        assert!(serde_urlencoded::from_str::<Msg>("level=loud").is_err());
+    }
+
+    #[test]
+    fn test_deserialize_checks() {
+        let error = serde_urlencoded::from_str::<Msg>("title=Hi&key=1234").unwrap_err().to_string();
+        assert!(error.contains("missing encryption type"), "{}", error);
+        let error = serde_urlencoded::from_str::<Msg>("title=Hi&enc_type=aes256&mode=cbc&key=123456789012345678901234&iv=1234567890123456")
+            .unwrap_err().to_string();
+        assert!(error.contains("key must be 32 bytes, got 24"), "{}", error);
+        let error = serde_urlencoded::from_str::<Msg>("title=Hi&image=ftp%3A%2F%2Fexample.com%2Fa.png").unwrap_err().to_string();
+        assert!(error.contains("image: unsupported scheme ftp"), "{}", error);
+        assert!(serde_urlencoded::from_str::<Msg>("title=Hi&url=javascript%3Aalert(1)").is_err());
+        assert!(serde_urlencoded::from_str::<Msg>("title=Hi&url=bark%3A%2F%2F&image=https%3A%2F%2Fexample.com%2Fa.png").is_ok());
+    }
+}
\ No newline at end of file
@@ -0,0 +1,246 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+
+use std::{collections::{HashMap, VecDeque}, time::Duration};
+
+use crate::{bark::{Bark, SendResult}, msg::Msg};
+
+/// retries of transient failures by default
+pub const PIPELINE_RETRIES: u32 = 3;
+
+/// wait before the first retry, doubled on every further retry
+pub const PIPELINE_BACKOFF: Duration = Duration::from_millis(500);
+
+/// undeliverable notifications kept by default, the oldest are dropped when full
+pub const DEAD_LETTER_CAPACITY: usize = 1024;
+
+/// A [`Bark`] with a retry queue for transient failures and a dead-letter store
+/// for notifications that could not be delivered.
+///
+/// Failures without a status (network errors), `429` and `5xx` are retried with
+/// an exponential backoff; any other failure is permanent, a `410` is not a failure. Devices that fail
+/// permanently or run out of retries are kept, with their msg, in an in-memory
+/// ring buffer until [`NotificationPipeline::flush_dead_letters`] is called.
+///
+/// Through apns a `5xx` is already retried inside every send, see [`Bark::set_server_error_retries`],
+/// so a device answering `5xx` is tried up to `(server_error_retries + 1) * (max_retries + 1)` times.
+/// Lower one of the two to bound the attempts.
+///
+/// # Example
+/// ```rust,no_run
+/// use bark_dev::{bark::Bark, msg::Msg, pipeline::NotificationPipeline};
+///
+/// let mut pipeline = NotificationPipeline::new(Bark::new());
+/// pipeline.set_max_retries(5);
+///
+/// let rt = tokio::runtime::Runtime::new().unwrap();
+/// rt.block_on(pipeline.send(&Msg::new("title", "body"), vec!["the_device_token_get_from_bark_app".to_string()]));
+///
+/// for (msg, devices) in pipeline.flush_dead_letters() {
+///     println!("undeliverable to {:?}: {:?}", devices, msg);
+/// }
+/// ```
+pub struct NotificationPipeline {
+    bark: Bark,
+    max_retries: u32,
+    backoff: Duration,
+    capacity: usize,
+    dead_letters: VecDeque<(Msg, Vec<String>)>,
+}
+
+impl NotificationPipeline {
+    /// Wraps `bark` with the default retries, backoff and dead-letter capacity.
+    ///
+    /// # Arguments
+    /// - `bark`: The instance used to send.
+    ///
+    /// # Returns
+    /// The pipeline.
+    pub fn new(bark: Bark) -> Self {
+        NotificationPipeline {
+            bark,
+            max_retries: PIPELINE_RETRIES,
+            backoff: PIPELINE_BACKOFF,
+            capacity: DEAD_LETTER_CAPACITY,
+            dead_letters: VecDeque::new(),
+        }
+    }
+
+    /// Sets how many times a transient failure is retried.
+    ///
+    /// The retries of a `5xx` add to the ones of [`Bark::set_server_error_retries`].
+    ///
+    /// # Arguments
+    /// - `max_retries`: The retries per device, 0 to never retry.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_max_retries(&mut self, max_retries: u32) -> &mut Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    /// Sets the wait before the first retry, doubled on every further retry.
+    ///
+    /// # Arguments
+    /// - `backoff`: The initial wait.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_retry_backoff(&mut self, backoff: Duration) -> &mut Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets how many undeliverable notifications are kept, dropping the oldest when over.
+    ///
+    /// # Arguments
+    /// - `capacity`: The size of the dead-letter ring buffer.
+    ///
+    /// # Returns
+    /// A mutable reference to `self` for method chaining.
+    pub fn set_dead_letter_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.capacity = capacity;
+        while self.dead_letters.len() > capacity {
+            self.dead_letters.pop_front();
+        }
+        self
+    }
+
+    /// The wrapped instance, e.g. to register hooks or change the topic.
+    pub fn bark(&mut self) -> &mut Bark {
+        &mut self.bark
+    }
+
+    /// Sends `msg` to `devices`, retrying transient failures.
+    ///
+    /// # Arguments
+    /// - `msg`: The notification.
+    /// - `devices`: The device tokens, duplicates are sent once.
+    ///
+    /// # Returns
+    /// The last result of every device, the failed ones ended up in the dead-letter store.
+    pub async fn send<T>(&mut self, msg: &Msg, devices: T) -> Vec<SendResult>
+    where
+        T: IntoIterator<Item = String>
+    {
+        let mut results: Vec<SendResult> = self.bark.async_send(msg, devices).await;
+        let mut backoff: Duration = self.backoff;
+        for _ in 0..self.max_retries {
+            let transient: Vec<String> = results.iter().filter(|result| Self::is_transient(result)).map(|result| result.device.clone()).collect();
+            if transient.is_empty() {
+                break;
+            }
+            eprintln!("retrying {} devices in {}ms", transient.len(), backoff.as_millis());
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+            let mut retried: HashMap<String, SendResult> = self.bark
+                .async_send(msg, transient)
+                .await
+                .into_iter()
+                .map(|result| (result.device.clone(), result))
+                .collect();
+            for result in results.iter_mut() {
+                if let Some(retry) = retried.remove(&result.device) {
+                    *result = retry;
+                }
+            }
+        }
+        let dead: Vec<String> = results.iter().filter(|result| !result.success).map(|result| result.device.clone()).collect();
+        if !dead.is_empty() && self.capacity > 0 {
+            if self.dead_letters.len() == self.capacity {
+                self.dead_letters.pop_front();
+            }
+            self.dead_letters.push_back((msg.clone(), dead));
+        }
+        results
+    }
+
+    /// Takes the undeliverable notifications, oldest first, leaving the store empty.
+    ///
+    /// # Returns
+    /// Every msg with the devices it could not be delivered to.
+    pub fn flush_dead_letters(&mut self) -> Vec<(Msg, Vec<String>)> {
+        self.dead_letters.drain(..).collect()
+    }
+
+    /// failed by a network error, throttling or a server error, which may pass on a later try
+    fn is_transient(result: &SendResult) -> bool {
+        match result.status_code {
+            _ if result.success => false,
+            None => true,
+            Some(status) => status == 429 || status >= 500,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retries_then_dead_letters() {
+        let mut bark = Bark::new_apns();
+        bark.inject_response("ok", 200, None)
+            .inject_response("flaky", 429, Some("TooManyRequests"))
+            .inject_response("flaky", 200, None)
+            .inject_response("gone", 410, Some("Unregistered"))
+            .inject_response("down", 503, Some("ServiceUnavailable"))
+            .inject_response("down", 503, Some("ServiceUnavailable"));
+        let mut pipeline = NotificationPipeline::new(bark);
+        pipeline.set_max_retries(1).set_retry_backoff(Duration::from_millis(1));
+
+        let msg = Msg::new("title", "body");
+        let devices: Vec<String> = ["ok", "flaky", "gone", "down"].iter().map(|device| device.to_string()).collect();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let results = rt.block_on(pipeline.send(&msg, devices));
+        assert_eq!(
+            results.iter().map(|result| (result.device.as_str(), result.success, result.status_code)).collect::<Vec<_>>(),
+            vec![("ok", true, Some(200)), ("flaky", true, Some(200)), ("gone", true, Some(410)), ("down", false, Some(503))]
+        );
+
+        let dead = pipeline.flush_dead_letters();
+        assert_eq!(dead.len(), 1);
+        assert!(dead[0].0 == msg);
+        assert_eq!(dead[0].1, vec!["down".to_string()]);
+        assert!(pipeline.flush_dead_letters().is_empty());
+    }
+
+    #[test]
+    fn test_dead_letter_capacity() {
+        let mut bark = Bark::new_apns();
+        for title in ["a", "b", "c"] {
+            bark.inject_response(title, 400, Some("BadDeviceToken"));
+        }
+        let mut pipeline = NotificationPipeline::new(bark);
+        pipeline.set_dead_letter_capacity(2);
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        for title in ["a", "b", "c"] {
+            rt.block_on(pipeline.send(&Msg::new(title, "body"), vec![title.to_string()]));
+        }
+        let devices: Vec<Vec<String>> = pipeline.flush_dead_letters().into_iter().map(|(_, devices)| devices).collect();
+        assert_eq!(devices, vec![vec!["b".to_string()], vec!["c".to_string()]]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,155 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+use std::{fs, io::{Error, ErrorKind}, path::{Path, PathBuf}};
+
+/// A list of known device tokens persisted to a JSON file.
+///
+/// The file holds a JSON array of strings, e.g. `["token1","token2"]`.
+/// Devices are kept in insertion order without duplicates.
+///
+/// # Example
+/// ```rust,no_run
+/// use bark_dev::{bark::Bark, msg::Msg, registry::DeviceRegistry};
+///
+/// let mut registry = DeviceRegistry::open("devices.json").unwrap();
+/// registry.add("the_device_token_get_from_bark_app".to_string());
+/// registry.save().unwrap();
+///
+/// let mut bark = Bark::new();
+/// let results = bark.send_to_all_known_devices(&mut registry, &Msg::new("title", "body"));
+/// ```
+pub struct DeviceRegistry {
+    path: PathBuf,
+    devices: Vec<String>,
+}
+
+impl DeviceRegistry {
+    /// Opens the registry stored at `path`, starting empty if the file does not exist yet.
+    ///
+    /// # Arguments
+    /// - `path`: The JSON file backing the registry.
+    ///
+    /// # Returns
+    /// The registry, or an error if the file can not be read or is not a JSON array of strings.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        let mut registry: DeviceRegistry = DeviceRegistry { path, devices: Vec::new() };
+        match fs::read_to_string(&registry.path) {
+            Ok(content) => {
+                for device in Self::parse(&content)? {
+                    registry.add(device);
+                }
+                Ok(registry)
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(registry),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Adds a device, returns `false` if it was already known.
+    pub fn add(&mut self, device: String) -> bool {
+        let device: String = device.trim().to_string();
+        if device.is_empty() || self.devices.contains(&device) {
+            return false;
+        }
+        self.devices.push(device);
+        true
+    }
+
+    /// Removes a device, returns `false` if it was not known.
+    pub fn remove(&mut self, device: String) -> bool {
+        let len: usize = self.devices.len();
+        self.devices.retain(|known| known != device.trim());
+        self.devices.len() != len
+    }
+
+    /// Returns all known devices in insertion order.
+    pub fn all(&self) -> Vec<&str> {
+        self.devices.iter().map(|device| device.as_str()).collect()
+    }
+
+    /// Writes the registry back to its file.
+    pub fn save(&self) -> Result<(), Error> {
+        let devices: Vec<String> = self.devices.iter().map(|device| format!("\"{}\"", device)).collect();
+        fs::write(&self.path, format!("[{}]", devices.join(",")))
+    }
+
+    fn parse(content: &str) -> Result<Vec<String>, Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "device registry must be a JSON array of strings");
+        let content: &str = content.trim();
+        if content.is_empty() {
+            return Ok(Vec::new());
+        }
+        let inner: &str = content
+            .strip_prefix('[')
+            .and_then(|content| content.strip_suffix(']'))
+            .ok_or_else(invalid)?
+            .trim();
+        if inner.is_empty() {
+            return Ok(Vec::new());
+        }
+        inner
+            .split(',')
+            .map(|item| {
+                item.trim()
+                    .strip_prefix('"')
+                    .and_then(|item| item.strip_suffix('"'))
+                    .filter(|item| !item.contains('"') && !item.contains('\\'))
+                    .map(|item| item.to_string())
+                    .ok_or_else(invalid)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry_round_trip() {
+        let path: PathBuf = std::env::temp_dir().join(format!("bark-registry-{}.json", std::process::id()));
+        let mut registry = DeviceRegistry::open(&path).unwrap();
+        assert!(registry.all().is_empty());
+        assert!(registry.add("a".to_string()));
+        assert!(registry.add("b".to_string()));
+        assert!(!registry.add("a".to_string()));
+        registry.save().unwrap();
+
+        let mut registry = DeviceRegistry::open(&path).unwrap();
+        assert_eq!(registry.all(), vec!["a", "b"]);
+        assert!(registry.remove("a".to_string()));
+        assert!(!registry.remove("a".to_string()));
+        assert_eq!(registry.all(), vec!["b"]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_registry_rejects_invalid_file() {
+        assert!(DeviceRegistry::parse("{\"devices\":[]}").is_err());
+        assert!(DeviceRegistry::parse("[\"a\",b]").is_err());
+        assert_eq!(DeviceRegistry::parse(" [ \"a\" , \"b\" ] ").unwrap(), vec!["a", "b"]);
+    }
+}
\ No newline at end of file
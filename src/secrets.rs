@@ -0,0 +1,144 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+//! helpers shared by the secret manager integrations
+//!
+//! the secret is a json object `{"team_id": "...", "auth_key_id": "...", "key_pem": "..."}`
+//! with an optional `"topic"`
+
+use crate::{bark::Bark, config::{find_closing_quote, parse_flat_json, ConfigValue}, BarkError};
+
+/// new a bark instance from the secret json
+pub(crate) fn bark_from_secret(secret: &str) -> Result<Bark, BarkError> {
+    let entries: Vec<(String, ConfigValue)> = parse_flat_json(secret)?;
+    let field = |name: &str| -> Option<String> {
+        entries.iter().find_map(|(key, value)| match value {
+            ConfigValue::Str(value) if key == name => Some(value.clone()),
+            _ => None,
+        })
+    };
+    let required = |name: &str| field(name).ok_or_else(|| BarkError::InvalidConfiguration(format!("secret has no {}", name)));
+    let (team_id, auth_key_id, key_pem) = (required("team_id")?, required("auth_key_id")?, required("key_pem")?);
+    match field("topic") {
+        Some(topic) => Bark::with_credentials(&team_id, &auth_key_id, &topic, &key_pem),
+        None => Bark::new_production(&team_id, &auth_key_id, &key_pem),
+    }
+}
+
+/// send the request, the body of a 2xx answer
+pub(crate) async fn answer(request: reqwest::RequestBuilder, service: &str) -> Result<String, BarkError> {
+    let response: reqwest::Response = request.send().await?;
+    let status: reqwest::StatusCode = response.status();
+    let body: String = response.text().await?;
+    if !status.is_success() {
+        return Err(BarkError::Request(format!("{} answered {} {}", service, status.as_u16(), body)));
+    }
+    Ok(body)
+}
+
+/// the raw text of the value of the top-level `key` of a json object, strings keep their quotes
+/// 
+/// the answers of the secret managers nest the secret in objects next to arrays and nulls,
+/// which [`parse_flat_json`] does not read
+pub(crate) fn json_value<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let mut depth: usize = 0;
+    let mut index: usize = 0;
+    while index < json.len() {
+        match json.as_bytes()[index] {
+            b'"' => {
+                let end: usize = index + 1 + find_closing_quote(&json[index + 1..])?;
+                let after: &str = json[end + 1..].trim_start();
+                if depth == 1 && &json[index + 1..end] == key {
+                    if let Some(value) = after.strip_prefix(':') {
+                        let value: &str = value.trim_start();
+                        return Some(&value[..value_end(value)?]);
+                    }
+                }
+                index = end;
+            },
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth = depth.checked_sub(1)?,
+            _ => {},
+        }
+        index += 1;
+    }
+    None
+}
+
+/// the length of the json value at the start of `value`
+fn value_end(value: &str) -> Option<usize> {
+    match value.as_bytes().first()? {
+        b'"' => Some(find_closing_quote(&value[1..])? + 2),
+        b'{' | b'[' => {
+            let mut depth: usize = 0;
+            let mut index: usize = 0;
+            while index < value.len() {
+                match value.as_bytes()[index] {
+                    b'"' => index += find_closing_quote(&value[index + 1..])? + 1,
+                    b'{' | b'[' => depth += 1,
+                    b'}' | b']' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            return Some(index + 1);
+                        }
+                    },
+                    _ => {},
+                }
+                index += 1;
+            }
+            None
+        },
+        _ => Some(value.find([',', '}', ']', ' ', '\n', '\r', '\t']).unwrap_or(value.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escape(str: &str) -> String {
+        str.replace('\n', "\\n")
+    }
+
+    #[test]
+    fn test_bark_from_secret() {
+        let pem: String = Bark::generate_test_keypair().0.key_pem().to_string();
+        let secret: String = format!("{{\"team_id\": \"TEAM\", \"auth_key_id\": \"KEY\", \"key_pem\": \"{}\"}}", escape(&pem));
+        assert!(bark_from_secret(&secret).is_ok());
+        let secret: String = format!("{{\"team_id\": \"TEAM\", \"auth_key_id\": \"KEY\", \"topic\": \"com.example.app\", \"key_pem\": \"{}\"}}", escape(&pem));
+        assert!(bark_from_secret(&secret).is_ok());
+        assert!(matches!(bark_from_secret("{\"team_id\": \"TEAM\"}"), Err(BarkError::InvalidConfiguration(_))));
+    }
+
+    #[test]
+    fn test_json_value() {
+        let json: &str = r#"{"request_id": "data", "data": {"data": {"team_id": "TEAM", "key_pem": "a\"}b"}, "metadata": {"version": 3}}, "warnings": null, "ttl": 60}"#;
+        let data: &str = json_value(json, "data").unwrap();
+        assert_eq!(json_value(data, "data"), Some(r#"{"team_id": "TEAM", "key_pem": "a\"}b"}"#));
+        assert_eq!(json_value(json_value(data, "metadata").unwrap(), "version"), Some("3"));
+        assert_eq!(json_value(json, "warnings"), Some("null"));
+        assert_eq!(json_value(json, "ttl"), Some("60"));
+        assert_eq!(json_value(json, "request_id"), Some("\"data\""));
+        assert_eq!(json_value(json, "team_id"), None);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,82 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+use crate::{apns::{Failure, Middleware}, msg::Msg};
+use indexmap::IndexMap;
+use reqwest::header::HeaderValue;
+
+/// the client shared by the sends to a bark server
+pub fn client() -> Result<reqwest::Client, reqwest::Error> {
+    reqwest::ClientBuilder::new().build()
+}
+
+/// async send to devices through a bark server
+/// 
+/// `payload` is sent to every device instead of the msg serialized for it
+/// 
+/// return: the failed devices and why they failed, empty if all success
+pub async fn async_send<T>(client: &reqwest::Client, msg: &Msg, server: &str, devices: T, middleware: &Middleware, payload: Option<&str>) -> IndexMap<String, Failure> 
+where 
+    T: IntoIterator<Item = String>
+{
+    let url: String = format!("{}/push", server.trim_end_matches('/'));
+
+    let mut results: IndexMap<String, Failure> = IndexMap::new();
+    for device in devices {
+        let body: String = match payload {
+            Some(payload) => payload.to_string(),
+            None => match msg.serialize_for_server(&device) {
+                Ok(body) => body,
+                Err(e) => {
+                    eprintln!("send to {} failed: {}", device, e);
+                    results.insert(device, Failure::new(None, e.to_string()));
+                    continue;
+                }
+            },
+        };
+        let req: reqwest::RequestBuilder = 
+                client
+                    .post(url.as_str())
+                    .header(reqwest::header::CONTENT_TYPE, HeaderValue::from_static("application/json; charset=utf-8"))
+                    .body(body);
+        let resp = middleware.send(req).await;
+        match resp {
+            Ok(resp) => {
+                middleware.inspect(&device, &resp);
+                if ! resp.status().is_success() {
+                    let sc: u16 = resp.status().as_u16();
+                    let text: String = resp.text().await.unwrap_or_default();
+                    middleware.inspect_body(&text);
+                    eprintln!("send to {} failed: {} {}", device, sc, text);
+                    results.insert(device, Failure::new(Some(sc), text));
+                }
+            },
+            Err(e) => {
+                eprintln!("send to {} failed: {}", device, e);
+                results.insert(device, Failure::new(None, e.to_string()));
+            }
+        }
+    }
+    results
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+
+
+use std::{future::Future, pin::Pin, sync::Arc, task::{Context, Poll}};
+
+use tokio::sync::Mutex;
+use tower_service::Service;
+
+use crate::{bark::{Bark, SendResult}, msg::Msg, BarkError};
+
+/// A msg and the devices to send it to, the request of a [`BarkService`].
+pub struct NotificationRequest(pub Msg, pub Vec<String>);
+
+/// A [`Bark`] instance as a `tower::Service`, to compose tower middleware
+/// (timeout, rate limiting, retry, ...) over sends with `tower::ServiceBuilder`.
+///
+/// Clones share the instance, requests are sent one after another as
+/// [`Bark::async_send`] needs it mutably. The response is the result of every device.
+///
+/// # Example
+/// ```rust,no_run
+/// use bark_dev::{bark::Bark, msg::Msg, service::{BarkService, NotificationRequest}};
+/// use tower_service::Service;
+///
+/// let mut service = BarkService::new(Bark::new());
+/// let request = NotificationRequest(Msg::new("title", "body"), vec!["the_device_token_get_from_bark_app".to_string()]);
+/// let rt = tokio::runtime::Runtime::new().unwrap();
+/// let results = rt.block_on(service.call(request)).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct BarkService {
+    bark: Arc<Mutex<Bark>>,
+}
+
+impl BarkService {
+    /// Wraps `bark` into a service.
+    ///
+    /// # Arguments
+    /// - `bark`: The instance used to send.
+    ///
+    /// # Returns
+    /// The service.
+    pub fn new(bark: Bark) -> Self {
+        BarkService { bark: Arc::new(Mutex::new(bark)) }
+    }
+
+    /// The wrapped instance, e.g. to register hooks or read the failure counts.
+    pub fn bark(&self) -> Arc<Mutex<Bark>> {
+        self.bark.clone()
+    }
+}
+
+impl Service<NotificationRequest> for BarkService {
+    type Response = Vec<SendResult>;
+    type Error = BarkError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: NotificationRequest) -> Self::Future {
+        let bark: Arc<Mutex<Bark>> = self.bark.clone();
+        Box::pin(async move {
+            let NotificationRequest(msg, devices) = request;
+            Ok(bark.lock().await.async_send(&msg, devices).await)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_call() {
+        let mut bark = Bark::new_apns();
+        bark.inject_response("ok", 200, None).inject_response("gone", 410, Some("Unregistered"));
+        let mut service = BarkService::new(bark);
+        let mut clone = service.clone();
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let request = NotificationRequest(Msg::new("title", "body"), vec!["ok".to_string(), "gone".to_string()]);
+        let results = rt.block_on(service.call(request)).unwrap();
+        assert_eq!(
+            results.iter().map(|result| (result.device.as_str(), result.success, result.unregistered_at)).collect::<Vec<_>>(),
+            vec![("ok", true, None), ("gone", true, Some(0))]
+        );
+        let request = NotificationRequest(Msg::new("title", "body"), vec![]);
+        assert!(rt.block_on(clone.call(request)).unwrap().is_empty());
+    }
+}
\ No newline at end of file
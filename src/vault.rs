@@ -0,0 +1,107 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+
+//! load bark credentials from a hashicorp vault kv v2 secret, see [`crate::bark::Bark::new_from_vault`]
+
+use crate::{secrets::{answer, json_value}, BarkError};
+
+const VAULT_TOKEN: &str = "X-Vault-Token";
+/// a renewable token expiring within this many seconds is renewed before the secret is read
+const RENEW_BELOW: u64 = 300;
+const RENEW_INCREMENT: &str = "1h";
+
+/// read the secret at `path`, `<mount>/<path>` like `secret/bark`
+/// 
+/// return : the json object of the latest version of the secret
+pub(crate) async fn read_secret(vault_url: &str, token: &str, path: &str) -> Result<String, BarkError> {
+    let client: reqwest::Client = reqwest::Client::new();
+    let vault_url: &str = vault_url.trim_end_matches('/');
+    let (mount, path) = path
+        .trim_matches('/')
+        .split_once('/')
+        .ok_or_else(|| BarkError::InvalidConfiguration(format!("vault path {} is not <mount>/<path>", path)))?;
+    renew_if_expiring(&client, vault_url, token).await?;
+    let body: String = answer(client.get(format!("{}/v1/{}/data/{}", vault_url, mount, path)).header(VAULT_TOKEN, token), "vault").await?;
+    json_value(&body, "data")
+        .and_then(|data| json_value(data, "data"))
+        .map(str::to_string)
+        .ok_or_else(|| BarkError::Request(format!("vault answered without a secret: {}", body)))
+}
+
+/// renew the token if it is renewable and expires within [`RENEW_BELOW`] seconds
+/// 
+/// root tokens have a ttl of 0 and never expire
+async fn renew_if_expiring(client: &reqwest::Client, vault_url: &str, token: &str) -> Result<(), BarkError> {
+    let body: String = answer(client.get(format!("{}/v1/auth/token/lookup-self", vault_url)).header(VAULT_TOKEN, token), "vault").await?;
+    let data: &str = json_value(&body, "data").unwrap_or("{}");
+    let ttl: u64 = json_value(data, "ttl").and_then(|ttl| ttl.parse::<u64>().ok()).unwrap_or(0);
+    if ttl == 0 || ttl >= RENEW_BELOW || json_value(data, "renewable") != Some("true") {
+        return Ok(());
+    }
+    answer(
+        client
+            .post(format!("{}/v1/auth/token/renew-self", vault_url))
+            .header(VAULT_TOKEN, token)
+            .body(format!("{{\"increment\":\"{}\"}}", RENEW_INCREMENT)),
+        "vault",
+    ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::serve_http;
+
+    fn secret_answer(request_line: &str, ttl: u64) -> String {
+        if request_line.contains("lookup-self") {
+            format!("{{\"data\": {{\"policies\": [\"default\"], \"renewable\": true, \"ttl\": {}, \"meta\": null}}}}", ttl)
+        } else if request_line.contains("renew-self") {
+            "{\"auth\": {\"lease_duration\": 3600, \"renewable\": true}}".to_string()
+        } else {
+            "{\"data\": {\"data\": {\"team_id\": \"TEAM\"}, \"metadata\": {\"version\": 2}}}".to_string()
+        }
+    }
+
+    #[test]
+    fn test_read_secret() {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+
+        let (url, server) = serve_http(2, |line, _| (200, secret_answer(line, 3600)));
+        assert_eq!(rt.block_on(read_secret(&url, "token", "/secret/bark")).unwrap(), "{\"team_id\": \"TEAM\"}");
+        assert_eq!(server.join().unwrap().into_iter().map(|(line, _)| line).collect::<Vec<_>>(), vec![
+            "GET /v1/auth/token/lookup-self HTTP/1.1".to_string(),
+            "GET /v1/secret/data/bark HTTP/1.1".to_string(),
+        ]);
+
+        let (url, server) = serve_http(3, |line, _| (200, secret_answer(line, 60)));
+        assert!(rt.block_on(read_secret(&url, "token", "secret/apps/bark")).is_ok());
+        assert_eq!(server.join().unwrap().into_iter().map(|(line, _)| line).collect::<Vec<_>>(), vec![
+            "GET /v1/auth/token/lookup-self HTTP/1.1".to_string(),
+            "POST /v1/auth/token/renew-self HTTP/1.1".to_string(),
+            "GET /v1/secret/data/apps/bark HTTP/1.1".to_string(),
+        ]);
+
+        assert!(matches!(rt.block_on(read_secret(&url, "token", "bark")), Err(BarkError::InvalidConfiguration(_))));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,113 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Sends against a mock apns, without apple credentials.
+
+mod mock_apns;
+
+use std::sync::{Arc, Mutex};
+
+use bark_dev::{
+    bark::{ApnsErrorReason, Bark, SendResult, SendResults},
+    msg::Msg,
+};
+use mock_apns::MockApns;
+
+/// a bark instance signing with a fresh key and the mock verifying with its public key
+fn setup() -> (Bark, MockApns) {
+    let (mut bark, public_key) = Bark::generate_test_keypair();
+    let apns: MockApns = MockApns::start(&public_key);
+    bark.with_interceptor(apns.interceptor());
+    (bark, apns)
+}
+
+fn devices(devices: &[&str]) -> Vec<String> {
+    devices.iter().map(|device| device.to_string()).collect()
+}
+
+#[test]
+fn test_send() {
+    let (mut bark, apns) = setup();
+    let results: Vec<SendResult> = bark.send(&Msg::new("title", "body"), devices(&["a", "b"]));
+    assert!(results.all_succeeded());
+    assert!(results.iter().all(|result| result.status_code == Some(200) && result.apns_id.is_some()));
+
+    let received = apns.received();
+    assert_eq!(received.iter().map(|request| request.path.as_str()).collect::<Vec<_>>(), vec!["/3/device/a", "/3/device/b"]);
+    assert!(received.iter().all(|request| request.token_valid));
+    assert_eq!(received[0].headers.get("apns-topic").unwrap(), bark.topic());
+    assert_eq!(received[0].headers.get("apns-push-type").unwrap(), "alert");
+    assert!(received[0].body.contains("\"title\":\"title\""));
+}
+
+#[test]
+fn test_async_send() {
+    let (mut bark, apns) = setup();
+    apns.script("b", 400, Some("{\"reason\":\"BadDeviceToken\"}"));
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let results: Vec<SendResult> = rt.block_on(bark.async_send(&Msg::new("title", "body"), devices(&["a", "b"])));
+    assert_eq!(results.iter().map(|result| (result.device.as_str(), result.success)).collect::<Vec<_>>(), vec![("a", true), ("b", false)]);
+    assert_eq!(results[1].status_code, Some(400));
+    assert_eq!(results[1].apns_reason, Some(ApnsErrorReason::BadDeviceToken));
+    assert_eq!(bark.device_failure_counts().get("b"), Some(&1));
+}
+
+#[test]
+fn test_server_errors_are_retried() {
+    let (mut bark, apns) = setup();
+    bark.set_server_error_retries(1);
+    apns.script("a", 503, Some("{\"reason\":\"ServiceUnavailable\"}"));
+    apns.script("b", 500, Some("{\"reason\":\"InternalServerError\"}")).script("b", 500, Some("{\"reason\":\"InternalServerError\"}"));
+    let results: Vec<SendResult> = bark.send(&Msg::new("title", "body"), devices(&["a", "b"]));
+    assert_eq!(results.iter().map(|result| (result.status_code, result.success)).collect::<Vec<_>>(), vec![(Some(200), true), (Some(500), false)]);
+    assert_eq!(apns.received().len(), 4);
+}
+
+#[test]
+fn test_unregistered() {
+    let (mut bark, apns) = setup();
+    apns.script("gone", 410, Some("{\"reason\":\"Unregistered\",\"timestamp\":1700000000000}"));
+    let results: Vec<SendResult> = bark.send(&Msg::new("title", "body"), devices(&["gone"]));
+    assert!(results[0].success);
+    assert_eq!(results[0].status_code, Some(410));
+    assert_eq!(results[0].apns_reason, Some(ApnsErrorReason::Unregistered));
+    assert_eq!(results[0].unregistered_at, Some(1700000000000));
+}
+
+#[test]
+fn test_rejected_token_is_refreshed_once() {
+    // signed with a key the mock does not know
+    let (mut bark, _) = Bark::generate_test_keypair();
+    let (_, public_key) = Bark::generate_test_keypair();
+    let apns: MockApns = MockApns::start(&public_key);
+    bark.with_interceptor(apns.interceptor());
+    let reported = Arc::new(Mutex::new(Vec::new()));
+    let seen = reported.clone();
+    bark.after_send(move |_: &Msg, results: &[SendResult]| seen.lock().unwrap().extend(results.iter().map(|result| result.success)));
+
+    let results: Vec<SendResult> = bark.send(&Msg::new("title", "body"), devices(&["a"]));
+    assert_eq!(results[0].status_code, Some(403));
+    assert_eq!(results[0].apns_reason, Some(ApnsErrorReason::InvalidProviderToken));
+    assert_eq!(apns.received().len(), 2);
+    assert!(apns.received().iter().all(|request| !request.token_valid));
+    assert_eq!(*reported.lock().unwrap(), vec![false]);
+}
\ No newline at end of file
@@ -0,0 +1,193 @@
+// MIT License
+//
+// Copyright (c) 2025 66f94eae
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! A mock apns answering http/2 requests on a local port.
+//!
+//! Requests reach it through [`MockApns::interceptor`], which rewrites the url of every
+//! request to the mock and keeps its path, so `/3/device/<token>` still names the device.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use bytes::Bytes;
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Verifier};
+
+/// a request the mock received
+#[derive(Clone, Debug)]
+pub struct Received {
+    pub path: String,
+    /// the device token of a `/3/device/<token>` path, else empty
+    pub device: String,
+    pub headers: http::HeaderMap,
+    pub body: String,
+    /// whether the bearer token was signed by the key the mock verifies with
+    pub token_valid: bool,
+}
+
+#[derive(Default)]
+struct State {
+    received: Vec<Received>,
+    /// (status, body) answered per device, in order, 200 once empty
+    script: HashMap<String, VecDeque<(u16, Option<String>)>>,
+}
+
+/// An apns mock verifying provider tokens and answering like a script says.
+///
+/// A request with a token not signed by the verifying key is answered 403 `InvalidProviderToken`,
+/// any other with the next scripted answer of its device, or 200.
+pub struct MockApns {
+    port: u16,
+    state: Arc<Mutex<State>>,
+    /// serves the mock until dropped
+    _runtime: tokio::runtime::Runtime,
+}
+
+impl MockApns {
+    /// Starts the mock on a free local port.
+    ///
+    /// # Arguments
+    /// - `public_key`: The DER public key tokens are verified with, see `Bark::generate_test_keypair`.
+    pub fn start(public_key: &[u8]) -> Self {
+        let public_key: PKey<openssl::pkey::Public> = PKey::public_key_from_der(public_key).expect("invalid public key");
+        let runtime: tokio::runtime::Runtime = tokio::runtime::Runtime::new().expect("start runtime failed");
+        let listener: tokio::net::TcpListener = runtime
+            .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+            .expect("bind failed");
+        let port: u16 = listener.local_addr().expect("bind failed").port();
+        let state: Arc<Mutex<State>> = Arc::default();
+        runtime.spawn(serve(listener, state.clone(), Arc::new(public_key)));
+        Self { port, state, _runtime: runtime }
+    }
+
+    /// Queues the answer to the next request for `device`.
+    pub fn script(&self, device: &str, status: u16, body: Option<&str>) -> &Self {
+        self.state
+            .lock()
+            .unwrap()
+            .script
+            .entry(device.to_string())
+            .or_default()
+            .push_back((status, body.map(str::to_string)));
+        self
+    }
+
+    /// Every request received so far, in the order they arrived.
+    pub fn received(&self) -> Vec<Received> {
+        self.state.lock().unwrap().received.clone()
+    }
+
+    /// An interceptor for `Bark::with_interceptor` sending every request to the mock.
+    pub fn interceptor(&self) -> impl Fn(reqwest::RequestBuilder) -> reqwest::RequestBuilder + Send + Sync + 'static {
+        let port: u16 = self.port;
+        move |req: reqwest::RequestBuilder| {
+            let (client, req) = req.build_split();
+            let mut req: reqwest::Request = req.expect("invalid request");
+            let url: &mut reqwest::Url = req.url_mut();
+            url.set_scheme("http").expect("set scheme failed");
+            url.set_host(Some("127.0.0.1")).expect("set host failed");
+            url.set_port(Some(port)).expect("set port failed");
+            reqwest::RequestBuilder::from_parts(client, req)
+        }
+    }
+}
+
+async fn serve(listener: tokio::net::TcpListener, state: Arc<Mutex<State>>, public_key: Arc<PKey<openssl::pkey::Public>>) {
+    while let Ok((stream, _)) = listener.accept().await {
+        let (state, public_key) = (state.clone(), public_key.clone());
+        tokio::spawn(async move {
+            let Ok(mut connection) = h2::server::handshake(stream).await else {
+                return;
+            };
+            while let Some(Ok((request, respond))) = connection.accept().await {
+                tokio::spawn(answer(request, respond, state.clone(), public_key.clone()));
+            }
+        });
+    }
+}
+
+async fn answer(
+    request: http::Request<h2::RecvStream>,
+    mut respond: h2::server::SendResponse<Bytes>,
+    state: Arc<Mutex<State>>,
+    public_key: Arc<PKey<openssl::pkey::Public>>,
+) {
+    let (parts, mut stream) = request.into_parts();
+    let mut body: Vec<u8> = Vec::new();
+    while let Some(Ok(chunk)) = stream.data().await {
+        let _ = stream.flow_control().release_capacity(chunk.len());
+        body.extend_from_slice(&chunk);
+    }
+    let path: String = parts.uri.path().to_string();
+    let token: &str = parts
+        .headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+    let received: Received = Received {
+        device: path.strip_prefix("/3/device/").unwrap_or_default().to_string(),
+        path,
+        token_valid: verify(token, &public_key),
+        headers: parts.headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    };
+
+    let (status, body, id): (u16, Option<String>, usize) = {
+        let mut state = state.lock().unwrap();
+        let (status, body) = match received.token_valid {
+            false => (403, Some("{\"reason\":\"InvalidProviderToken\"}".to_string())),
+            true => state.script.get_mut(&received.device).and_then(|answers| answers.pop_front()).unwrap_or((200, None)),
+        };
+        state.received.push(received);
+        (status, body, state.received.len())
+    };
+    let response: http::Response<()> = http::Response::builder()
+        .status(status)
+        .header("apns-id", format!("00000000-0000-0000-0000-{:012}", id))
+        .body(())
+        .expect("invalid response");
+    let Ok(mut send) = respond.send_response(response, body.is_none()) else {
+        return;
+    };
+    if let Some(body) = body {
+        let _ = send.send_data(Bytes::from(body), true);
+    }
+}
+
+/// whether `token` is a jwt `header.claims.signature` signed by `public_key`
+fn verify(token: &str, public_key: &PKey<openssl::pkey::Public>) -> bool {
+    let Some((signed, signature)) = token.rsplit_once('.') else {
+        return false;
+    };
+    let padded: String = format!("{}{}", signature, "=".repeat((4 - signature.len() % 4) % 4)).replace('-', "+").replace('_', "/");
+    let Ok(signature) = openssl::base64::decode_block(&padded) else {
+        return false;
+    };
+    Verifier::new(MessageDigest::sha256(), public_key)
+        .and_then(|mut verifier| {
+            verifier.update(signed.as_bytes())?;
+            verifier.verify(&signature)
+        })
+        .unwrap_or(false)
+}
\ No newline at end of file